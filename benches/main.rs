@@ -1,9 +1,17 @@
 use std::io::Write;
 
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{
+    black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput,
+};
 use hashlife::life::Life;
 
-criterion_group!(bench_main, clock_pattern, clock_render);
+criterion_group!(
+    bench_main,
+    clock_pattern,
+    clock_threads,
+    clock_node_store,
+    clock_render
+);
 criterion_main!(bench_main);
 
 pub fn clock_pattern(c: &mut Criterion) {
@@ -11,12 +19,65 @@ pub fn clock_pattern(c: &mut Criterion) {
 
     c.bench_function("clock step", |b| {
         b.iter(|| {
-            life.step();
+            life.step().unwrap();
             black_box(life.root.alive())
         })
     });
 }
 
+// Each run starts from a fresh universe, so nothing is already cached
+pub fn clock_threads(c: &mut Criterion) {
+    let mut group = c.benchmark_group("clock 1024 generations");
+    for threads in [1, 2, 4, 8] {
+        group.bench_with_input(
+            BenchmarkId::new("threads", threads),
+            &threads,
+            |b, &threads| {
+                b.iter_batched(
+                    || {
+                        let mut life = Life::from_rle(include_str!("../patterns/clock.rle"));
+                        life.set_threads(threads);
+                        life
+                    },
+                    |mut life| {
+                        life.step_by(1024).unwrap();
+                        black_box(life.root.alive())
+                    },
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+// Throughput is counted in nodes created, which are also reported with the memory they
+// take up
+pub fn clock_node_store(c: &mut Criterion) {
+    let fresh = || Life::from_rle(include_str!("../patterns/clock.rle"));
+    let mut life = fresh();
+    life.step_by(1024).unwrap();
+    let nodes = life.node_count();
+    println!(
+        "clock node store: {nodes} nodes, {} bytes per node",
+        life.memory_usage() / nodes
+    );
+
+    let mut group = c.benchmark_group("clock node store");
+    group.throughput(Throughput::Elements(nodes as u64));
+    group.bench_function("1024 generations", |b| {
+        b.iter_batched(
+            fresh,
+            |mut life| {
+                life.step_by(1024).unwrap();
+                black_box(life.node_count())
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
 pub fn clock_render(c: &mut Criterion) {
     let life = Life::from_rle(include_str!("../patterns/clock.rle"));
 
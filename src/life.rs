@@ -1,253 +1,963 @@
+mod boolean;
+mod bounded;
 mod cell;
 mod cell_id;
+mod edit;
+pub mod export;
+pub mod gc;
 pub mod import;
 mod layer;
+mod leaf;
 pub mod pack_unpack;
+pub mod paste;
+pub mod region;
+pub mod rule;
+pub mod transform;
 mod tree;
 
-use cell::Cell;
-use cell_id::CellId;
-use layer::Layer;
+use std::{collections::HashMap, fmt};
+
+use cell::{BaseCell, Cell};
+use cell_id::{CellId, CellIndexOverflow};
+use gc::EvictionStats;
+use layer::{Layer, Node};
+use leaf::{CountRule, LeafCell, TableRule, MAX_LEAF_LAYER};
+use region::UnboundedEdit;
+use rule::{B0Emulation, Rule, RuleParseError, Topology};
+
+/// Returned when stepping would carry cells beyond the range of i64 coordinates.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct CoordinateOverflow;
+
+impl fmt::Display for CoordinateOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The pattern has grown beyond the range of cell coordinates"
+        )
+    }
+}
+
+impl std::error::Error for CoordinateOverflow {}
 
 #[derive(Clone)]
 pub struct Life {
     pub root: CellId,
+    // Position of the corner shared by the root's quadrants, which moves as the root
+    // is trimmed
+    pub root_centre: (i64, i64),
     pub layers: Vec<Layer>,
     pub base_alive: CellId,
     pub base_dead: CellId,
+    generation: u128,
+    gc_threshold: Option<usize>,
+    // Node count above which the next collection runs, raised above the threshold while
+    // the live set alone comes close to it
+    next_gc_at: usize,
+    // Bytes of nodes and cached results above which old results are evicted
+    memory_budget: Option<usize>,
+    // Bytes above which results are next evicted, raised above the budget while the
+    // live set alone doesn't fit in it
+    next_eviction_at: usize,
+    // Counts the steps taken, to tell how long ago a cached result was last used
+    epoch: u32,
+    eviction_stats: EvictionStats,
+    rule: Rule,
+    topology: Topology,
+    b0_emulation: B0Emulation,
+    // Rules stepping the stored cells from each phase of the B0 emulation
+    phase_rules: [Rule; 2],
+    // Bitwise forms of the phase rules, for rules counting live neighbours
+    phase_count_rules: Option<[CountRule; 2]>,
+    // Tabulated phase rules, for any other two state rule which is stepped 4x4 blocks
+    // at a time
+    phase_tables: Option<[TableRule; 2]>,
+    // A cell can be advanced by up to 2^(layer - step_offset) generations, which
+    // grows with the radius of the rule
+    step_offset: usize,
+    // Layer of the cells advanced directly by the rule
+    base_layer: usize,
+    // Layer of the bottom cells of the tree, which are packed into leaves above layer 0
+    leaf_layer: usize,
+    // Leaf layer used whenever the rule has two states
+    packed_leaf_layer: usize,
+    threads: usize,
 }
 
 impl Life {
-    pub fn new(max_depth: u8) -> Self {
-        let mut layers = Vec::new();
-
-        for _ in 0..max_depth {
-            layers.push(Layer::default());
-        }
-
-        let base_alive = Cell::Base(cell::BaseCell::Alive);
-        let base_dead = Cell::Base(cell::BaseCell::Dead);
-        let base_alive = CellId::new(0, layers[0].add_cell(base_alive), 1);
-        let base_dead = CellId::new(0, layers[0].add_cell(base_dead), 0);
+    const DEFAULT_GC_THRESHOLD: usize = 1 << 22;
+    const DEFAULT_LEAF_LAYER: usize = 3;
+    // Widest root stepped, which keeps the coordinates of its cells and the widths of
+    // its cells within i64
+    const MAX_ROOT_LAYER: usize = 62;
+    // Cells below this layer are too quick to step to be worth handing to another thread
+    const MIN_THREADED_LAYER: usize = 8;
 
+    pub fn new() -> Self {
+        let placeholder = CellId::new(0, 0, 0).unwrap();
         let mut res = Life {
-            layers,
-            root: CellId::new(0, 0, 0),
-            base_alive,
-            base_dead,
+            layers: Vec::new(),
+            root: placeholder,
+            root_centre: (0, 0),
+            base_alive: placeholder,
+            base_dead: placeholder,
+            generation: 0,
+            gc_threshold: Some(Self::DEFAULT_GC_THRESHOLD),
+            next_gc_at: Self::DEFAULT_GC_THRESHOLD,
+            memory_budget: None,
+            next_eviction_at: usize::MAX,
+            epoch: 0,
+            eviction_stats: EvictionStats::default(),
+            rule: Rule::default(),
+            topology: Topology::Infinite,
+            b0_emulation: B0Emulation::None,
+            phase_rules: [Rule::default(), Rule::default()],
+            phase_count_rules: None,
+            phase_tables: None,
+            step_offset: 2,
+            base_layer: 2,
+            leaf_layer: Self::DEFAULT_LEAF_LAYER,
+            packed_leaf_layer: Self::DEFAULT_LEAF_LAYER,
+            threads: 1,
         };
 
-        let root = res.empty_of_layer(0);
+        res.reserve_layers(res.leaf_layer);
+        res.base_alive = res.add_cell(Cell::Base(BaseCell::ALIVE));
+        res.base_dead = res.add_cell(Cell::Base(BaseCell::DEAD));
+
+        // Centred on the origin with its leaves on their grid
+        let root = res.empty_of_layer(res.leaf_layer as u8 + 1);
         res.root = root;
+        res.update_phase_rules();
         res
     }
 
     pub fn from_rle(rle: &str) -> Self {
-        let pattern = import::rle_to_cell_positions(rle.to_string(), 0, 0);
-        Life::from_cell_positions(32, pattern)
+        Self::try_from_rle(rle).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    pub fn try_from_rle(rle: &str) -> Result<Self, RuleParseError> {
+        let (rule, topology) = match import::rle_rule(rle) {
+            Some(rule) => Rule::parse_with_topology(rule)?,
+            None => (Rule::default(), Topology::Infinite),
+        };
+
+        let mut life = Self::from_rle_with_rule(rle, rule);
+        let pattern = life.pattern_bounds();
+        life.set_topology(topology);
+
+        // Cells are kept where the header puts them, rather than clearing those outside
+        if let (Some(grid), Some(pattern)) = (life.grid_bounds(), pattern) {
+            if pattern.min_x < grid.min_x
+                || pattern.min_y < grid.min_y
+                || pattern.max_x > grid.max_x
+                || pattern.max_y > grid.max_y
+            {
+                return Err(RuleParseError::new(format!(
+                    "The pattern doesn't fit in the {topology} grid"
+                )));
+            }
+        }
+        Ok(life)
+    }
+
+    // Rules loaded from rule files can't be named in a header, so the header rule is ignored
+    pub fn from_rle_with_rule(rle: &str, rule: Rule) -> Self {
+        let (x, y) = import::rle_position(rle);
+        let pattern = import::rle_to_cell_states(rle.to_string(), x, y);
+        let mut life = Life::from_cell_states(pattern);
+        // Cells are written as they are stored, in the phase of the header's generation
+        life.generation = import::rle_generation(rle);
+        life.rule = rule;
+        life.update_phase_rules();
+        life
+    }
+
+    pub fn generation(&self) -> u128 {
+        self.generation
+    }
+
+    pub fn rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    /// Cached generations were computed under the old rule, so they are dropped. Cells
+    /// keep their displayed states, which fails when empty space would be in another
+    /// state under the new rule, as when leaving a B0 rule on a generation where it has
+    /// made empty space alive.
+    pub fn set_rule(&mut self, rule: Rule) -> Result<(), UnboundedEdit> {
+        let (b0_emulation, _) = self.b0_emulation_of(&rule);
+        if b0_emulation.phase_of(self.generation) != self.background_state() {
+            return Err(UnboundedEdit);
+        }
+        self.rule = rule;
+        self.update_phase_rules();
+        Ok(())
+    }
+
+    // Bounded grids clear everything outside of them each generation, so empty space
+    // turning alive does no harm there
+    fn b0_emulation_of(&self, rule: &Rule) -> (B0Emulation, [Rule; 2]) {
+        match self.topology {
+            Topology::Infinite => rule.b0_emulation(),
+            _ => (B0Emulation::None, [rule.clone(), rule.clone()]),
+        }
+    }
+
+    fn update_phase_rules(&mut self) {
+        (self.b0_emulation, self.phase_rules) = self.b0_emulation_of(&self.rule);
+
+        self.phase_count_rules = match self.phase_rules.each_ref().map(CountRule::new) {
+            [Some(first), Some(second)] => Some([first, second]),
+            _ => None,
+        };
+        self.update_leaf_layer();
+
+        // Building a table takes a while, so they are skipped when leaves are counted
+        // and shared between phases when the phases use the same rule
+        self.phase_tables = if self.leaf_layer > 0 && self.phase_count_rules.is_some() {
+            None
+        } else {
+            let first = TableRule::new(&self.phase_rules[0]);
+            let second = match self.phase_rules[1] == self.phase_rules[0] {
+                true => first.clone(),
+                false => TableRule::new(&self.phase_rules[1]),
+            };
+            first.zip(second).map(|(first, second)| [first, second])
+        };
+
+        // A cell's center can be advanced by one generation once the margin around it,
+        // a quarter of its width, covers the radius
+        self.step_offset = 2 + self.rule.radius().next_power_of_two().trailing_zeros() as usize;
+        // Leaves are only stepped as part of the square of four around them
+        self.base_layer = self.step_offset.max(self.leaf_layer + 1);
+
+        for layer in &mut self.layers {
+            layer.clear_next_gens();
+        }
+    }
+
+    // State of the cells not listed by `cell_positions` and `cell_states`. It is only
+    // alive for rules with B0, on generations where empty space is alive.
+    pub fn background_state(&self) -> u8 {
+        self.phase_of(self.generation)
+    }
+
+    fn phase_of(&self, generation: u128) -> u8 {
+        self.b0_emulation.phase_of(generation)
+    }
+
+    fn phase_after(&self, phase: u8, generations: u128) -> u8 {
+        match self.b0_emulation {
+            B0Emulation::None => 0,
+            B0Emulation::Alternating => ((phase as u128 + generations) % 2) as u8,
+            B0Emulation::Inverted if generations > 0 => 1,
+            B0Emulation::Inverted => phase,
+        }
+    }
+
+    /// Sets the width of the leaves which the cells of two state universes are packed
+    /// into, a power of two up to 16. A width of 1 stores every cell in its own node.
+    pub fn set_leaf_size(&mut self, size: usize) {
+        assert!(
+            size.is_power_of_two() && size <= 1 << MAX_LEAF_LAYER,
+            "Unsupported leaf size {size}"
+        );
+        self.packed_leaf_layer = size.trailing_zeros() as usize;
+        self.update_phase_rules();
+    }
+
+    // Rebuilds the bottom of the tree when switching between packed leaves and the
+    // single cells needed by rules with more than two states
+    fn update_leaf_layer(&mut self) {
+        let leaf_layer = match self.rule.states() {
+            2 => self.packed_leaf_layer,
+            _ => 0,
+        };
+        if leaf_layer == self.leaf_layer {
+            return;
+        }
+
+        while self.root.layer() < leaf_layer {
+            self.root = self.padded(self.root);
+        }
+        self.leaf_layer = leaf_layer;
+        self.root = self.relayered(self.root, &mut HashMap::new());
+    }
+
+    fn relayered(&mut self, cell_id: CellId, relayered: &mut HashMap<CellId, CellId>) -> CellId {
+        if let Some(&id) = relayered.get(&cell_id) {
+            return id;
+        }
+
+        let layer = cell_id.layer();
+        let res = match self.get_cell(cell_id).unwrap() {
+            Cell::Composite(cell) if layer > self.leaf_layer => {
+                let children = [cell.nw, cell.ne, cell.sw, cell.se];
+                let [nw, ne, sw, se] = children.map(|child| self.relayered(child, relayered));
+                self.join(nw, ne, sw, se)
+            }
+            _ => {
+                let width = 1 << layer;
+                let mut cells = vec![0; width * width];
+                self.read_states(cell_id, &mut cells, width, (0, 0));
+                self.build_from_states(&cells, width, (0, 0), layer)
+            }
+        };
+        relayered.insert(cell_id, res);
+        res
+    }
+
+    pub fn topology(&self) -> Topology {
+        self.topology
     }
 
+    // Cells outside a bounded grid are removed
+    pub fn set_topology(&mut self, topology: Topology) {
+        self.topology = topology;
+        self.update_phase_rules();
+        self.clear_outside_grid();
+    }
+
+    // Number of nodes after which garbage is collected between steps, None disables collection
+    pub fn set_gc_threshold(&mut self, threshold: Option<usize>) {
+        self.gc_threshold = threshold;
+        self.next_gc_at = threshold.unwrap_or(usize::MAX);
+    }
+
+    /// Sets the number of threads each step is shared between. Every number of
+    /// threads gives the same results.
+    pub fn set_threads(&mut self, threads: usize) {
+        assert!(threads > 0, "Stepping needs at least one thread");
+        self.threads = threads;
+    }
+
+    // Layers between the base cells and the leaves are empty whenever cells are packed
     pub fn print_stats(&self) {
-        let mut total = 0;
         for (i, layer) in self.layers.iter().enumerate() {
-            if layer.size() == 0 {
-                break;
+            if layer.size() > 0 {
+                println!("Layer {}: {}", i, layer.size());
             }
-            total += layer.size();
-            println!("Layer {}: {}", i, layer.size());
         }
-        println!("Total: {total}");
+        println!("Total: {}", self.node_count());
     }
 
     fn empty_of_layer(&mut self, layer: u8) -> CellId {
-        let mut empty_id = self.add_cell(Cell::Base(cell::BaseCell::Dead));
-        for _ in 1..=layer {
+        self.reserve_layers(layer as usize);
+        let (mut empty_id, bottom) = if self.leaf_layer > 0 && layer as usize >= self.leaf_layer {
+            let leaf = LeafCell::empty(self.leaf_layer);
+            (self.add_cell(Cell::Leaf(leaf)), self.leaf_layer)
+        } else {
+            (self.add_cell(Cell::Base(BaseCell::DEAD)), 0)
+        };
+        for _ in bottom..layer as usize {
             empty_id = self.add_cell(Cell::composite(empty_id, empty_id, empty_id, empty_id));
         }
         empty_id
     }
 
-    pub fn add_cell(&mut self, cell: Cell) -> CellId {
+    // Layers are only added between steps, so stepping can share the universe between
+    // threads. Nothing above the layer of the root is needed to step it.
+    fn reserve_layers(&mut self, layer: usize) {
+        if layer >= self.layers.len() {
+            self.layers.resize_with(layer + 1, Layer::default);
+        }
+    }
+
+    pub fn add_cell(&self, cell: Cell) -> CellId {
+        self.try_add_cell(cell)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    pub fn try_add_cell(&self, cell: Cell) -> Result<CellId, CellIndexOverflow> {
         let layer = cell.layer() as usize;
         let alive = cell.alive_count();
-        let index = self.layers[layer].add_cell(cell);
+        let node = match cell {
+            Cell::Base(cell) => Node::Base(cell),
+            Cell::Leaf(leaf) => Node::Leaf(leaf),
+            Cell::Composite(cell) => {
+                Node::Composite([cell.nw, cell.ne, cell.sw, cell.se].map(|id| id.index() as u32))
+            }
+        };
+        let index = self
+            .layers
+            .get(layer)
+            .unwrap_or_else(|| panic!("Layer {layer} has not been reserved"))
+            .add_cell(node, alive)
+            .ok_or(CellIndexOverflow { layer })?;
         CellId::new(layer, index, alive)
     }
 
-    pub fn get_cell(&self, cell_id: CellId) -> Option<&Cell> {
-        self.layers.get(cell_id.layer())?.get_cell(cell_id.index())
+    pub fn get_cell(&self, cell_id: CellId) -> Option<Cell> {
+        let layer = cell_id.layer();
+        let cell = match self.layers.get(layer)?.get_cell(cell_id.index())? {
+            Node::Base(cell) => Cell::Base(cell),
+            Node::Leaf(leaf) => Cell::Leaf(leaf),
+            Node::Composite(children) => {
+                let [nw, ne, sw, se] =
+                    children.map(|index| self.stored_id(layer - 1, index as usize));
+                Cell::composite(nw, ne, sw, se)
+            }
+        };
+        Some(cell)
     }
 
-    pub fn step(&mut self) {
-        while !self.is_padded(self.root) {
-            self.root = self.padded(self.root);
+    // Populations are only stored with the cells themselves, so ids of children and
+    // results read them back from their layer
+    fn stored_id(&self, layer: usize, index: usize) -> CellId {
+        let alive = self.layers[layer].alive(index);
+        CellId::new(layer, index, alive).expect("Stored indices are always in range")
+    }
+
+    pub fn base_cell(&self, state: u8) -> CellId {
+        match state {
+            0 => self.base_dead,
+            1 => self.base_alive,
+            state => self.add_cell(Cell::Base(BaseCell(state))),
+        }
+    }
+
+    pub fn base_state(&self, cell_id: CellId) -> u8 {
+        if cell_id.alive() == 0 {
+            return 0;
+        }
+
+        match self.get_cell(cell_id) {
+            Some(Cell::Base(cell)) => cell.state(),
+            _ => unreachable!("Only base cells have a state"),
+        }
+    }
+
+    /// Advances the universe by a single generation.
+    pub fn step(&mut self) -> Result<(), CoordinateOverflow> {
+        self.step_by(1)
+    }
+
+    /// Advances the universe by exactly `generations` generations.
+    ///
+    /// The step is split into its power of two components, each of which is
+    /// computed with a single memoized hyperspeed jump. Bounded grids are
+    /// stepped one generation at a time, since their edges change every generation.
+    /// Fails if cells would leave the range of i64 coordinates, leaving the universe
+    /// at the last jump which fit.
+    pub fn step_by(&mut self, generations: u128) -> Result<(), CoordinateOverflow> {
+        if self.topology != Topology::Infinite {
+            for _ in 0..generations {
+                self.step_bounded()?;
+            }
+            return Ok(());
+        }
+
+        // Jumps are split further once they would need a root wider than the coordinates
+        let max_step_log2 = (Self::MAX_ROOT_LAYER - self.step_offset - 1) as u32;
+        let mut remaining = generations;
+        while remaining > 0 {
+            let step_log2 = remaining.trailing_zeros().min(max_step_log2);
+            self.step_pow2(step_log2 as u8)?;
+            remaining -= 1 << step_log2;
+        }
+        Ok(())
+    }
+
+    /// Advances the universe until it reaches `generation`.
+    pub fn advance_to(&mut self, generation: u128) -> Result<(), CoordinateOverflow> {
+        assert!(
+            generation >= self.generation,
+            "Cannot advance backwards from generation {} to {generation}",
+            self.generation
+        );
+        self.step_by(generation - self.generation)
+    }
+
+    fn step_pow2(&mut self, step_log2: u8) -> Result<(), CoordinateOverflow> {
+        self.collect_garbage_if_needed();
+        self.epoch = self.epoch.saturating_add(1);
+
+        while self.root.layer() < self.base_layer || !self.is_padded(self.root) {
+            self.pad_root()?;
+        }
+        self.pad_root()?;
+
+        // The pattern can spread by at most `radius` cells per generation, so the root
+        // needs a margin of radius * 2^step_log2 cells around it to avoid losing anything.
+        while self.root.layer() < step_log2 as usize + self.step_offset + 1 {
+            self.pad_root()?;
+        }
+
+        let phase = self.phase_of(self.generation);
+        self.root = self.next_generation_threaded(self.root, step_log2, phase, self.threads);
+        self.generation += 1 << step_log2;
+        self.trim();
+        Ok(())
+    }
+
+    // Pads the root as long as every cell of the padded root has i64 coordinates
+    fn pad_root(&mut self) -> Result<(), CoordinateOverflow> {
+        let layer = self.root.layer() + 1;
+        if layer > Self::MAX_ROOT_LAYER {
+            return Err(CoordinateOverflow);
+        }
+        let half_width = 1i64 << (layer - 1);
+        let fits = |centre: i64| {
+            centre.checked_sub(half_width).is_some() && centre.checked_add(half_width - 1).is_some()
+        };
+        if !fits(self.root_centre.0) || !fits(self.root_centre.1) {
+            return Err(CoordinateOverflow);
         }
         self.root = self.padded(self.root);
+        Ok(())
+    }
+
+    /// Shrinks the root to the smallest square around the pattern, down to four cells
+    /// above the leaves. The root may be recentred on the pattern, which moves
+    /// `root_centre` along with it so that every cell keeps its position.
+    pub fn trim(&mut self) {
+        let min_layer = self.leaf_layer + 2;
+        while self.root.layer() > min_layer {
+            if self.root.alive() == 0 {
+                self.root = self.empty_of_layer(min_layer as u8);
+                return;
+            }
+
+            // The centred square comes first, so the root only moves when it has to
+            let cell = self.get_cell(self.root).unwrap().into_composite();
+            let [nw, ne, sw, se] = [cell.nw, cell.ne, cell.sw, cell.se];
+            let squares = [
+                (self.centered_subnode(self.root), (0, 0)),
+                (nw, (-1, -1)),
+                (self.horizontal(nw, ne), (0, -1)),
+                (ne, (1, -1)),
+                (self.vertical(nw, sw), (-1, 0)),
+                (self.vertical(ne, se), (1, 0)),
+                (sw, (-1, 1)),
+                (self.horizontal(sw, se), (0, 1)),
+                (se, (1, 1)),
+            ];
+            let Some(&(square, (dx, dy))) = squares
+                .iter()
+                .find(|(square, _)| square.alive() == self.root.alive())
+            else {
+                return;
+            };
 
-        self.root = self.next_generation(self.root);
+            let quarter_width = 1 << (self.root.layer() - 2);
+            self.root_centre.0 += dx * quarter_width;
+            self.root_centre.1 += dy * quarter_width;
+            self.root = square;
+        }
     }
 
     pub fn padded(&mut self, cell_id: CellId) -> CellId {
-        if let Cell::Composite(cell) = self.get_cell(cell_id).unwrap().clone() {
-            let empty = self.empty_of_layer((cell_id.layer() - 1) as u8);
+        self.reserve_layers(cell_id.layer() + 1);
+        match self.get_cell(cell_id).unwrap().clone() {
+            Cell::Composite(cell) => {
+                let empty = self.empty_of_layer((cell_id.layer() - 1) as u8);
 
-            let nw = self.join(empty, empty, empty, cell.nw);
-            let ne = self.join(empty, empty, cell.ne, empty);
-            let sw = self.join(empty, cell.sw, empty, empty);
-            let se = self.join(cell.se, empty, empty, empty);
+                let nw = self.join(empty, empty, empty, cell.nw);
+                let ne = self.join(empty, empty, cell.ne, empty);
+                let sw = self.join(empty, cell.sw, empty, empty);
+                let se = self.join(cell.se, empty, empty, empty);
 
-            self.join(nw, ne, sw, se)
-        } else {
-            let empty = self.empty_of_layer(0);
-            self.join(cell_id, empty, empty, empty)
+                self.join(nw, ne, sw, se)
+            }
+            // Each quarter of the leaf moves into the corner of its own leaf
+            Cell::Leaf(leaf) => {
+                let empty = &LeafCell::empty(leaf.layer() as usize);
+                let [nw, ne, sw, se] = [
+                    [empty, empty, empty, &leaf],
+                    [empty, empty, &leaf, empty],
+                    [empty, &leaf, empty, empty],
+                    [&leaf, empty, empty, empty],
+                ]
+                .map(|quadrants| self.add_cell(Cell::Leaf(LeafCell::centered(quadrants))));
+                self.join(nw, ne, sw, se)
+            }
+            Cell::Base(_) => {
+                let empty = self.empty_of_layer(0);
+                self.join(cell_id, empty, empty, empty)
+            }
         }
     }
 
-    fn is_padded(&mut self, cell_id: CellId) -> bool {
+    fn is_padded(&self, cell_id: CellId) -> bool {
         let total = cell_id.alive();
         let center = self.centered_subnode(cell_id).alive();
         total - center == 0
     }
 
-    pub fn next_generation(&mut self, cell_id: CellId) -> CellId {
-        if cell_id.layer() < 2 {
-            unreachable!("Next generation should never be called with a base layer cell");
+    // Returns the center of the given cell, advanced by 2^step_log2 generations
+    // starting from the given phase of the B0 emulation. A step of
+    // 2^(layer - step_offset) is the full hyperspeed jump, smaller steps are taken by
+    // cropping to the center before recursing.
+    pub fn next_generation(&self, cell_id: CellId, step_log2: u8, phase: u8) -> CellId {
+        self.next_generation_threaded(cell_id, step_log2, phase, 1)
+    }
+
+    // Steps the cells above `MIN_THREADED_LAYER` by splitting the squares stepped at
+    // each layer between the given number of threads
+    fn next_generation_threaded(
+        &self,
+        cell_id: CellId,
+        step_log2: u8,
+        phase: u8,
+        threads: usize,
+    ) -> CellId {
+        let layer = cell_id.layer();
+        if layer < self.base_layer {
+            unreachable!("Next generation should never be called below the base layer");
         }
+        debug_assert!(step_log2 as usize <= layer - self.step_offset);
 
-        if let Some(next_gen_id) = self.layers[cell_id.layer()].get_next_gen(cell_id) {
-            return *next_gen_id;
+        let slowdown = (layer - self.step_offset - step_log2 as usize) as u8;
+        if let Some(next_gen) =
+            self.layers[layer].get_next_gen(cell_id.index(), slowdown, phase, self.epoch)
+        {
+            return self.stored_id(layer - 1, next_gen);
         }
 
-        if cell_id.layer() == 2 {
-            let cell = self.get_cell(cell_id).unwrap().as_composite();
-            let res = self.next_generation_base_case(cell.nw, cell.ne, cell.sw, cell.se);
-            self.layers[cell_id.layer()].cache_next_gen(cell_id, res);
+        if layer == self.base_layer {
+            let res = if layer == 2 && self.leaf_layer == 0 {
+                let cell = self.get_cell(cell_id).unwrap().into_composite();
+                self.next_generation_base_case(cell.nw, cell.ne, cell.sw, cell.se, phase)
+            } else if layer == self.leaf_layer + 1
+                && (self.phase_count_rules.is_some() || self.phase_tables.is_some())
+            {
+                self.next_generation_leaves(cell_id, step_log2, phase)
+            } else {
+                self.next_generation_block(cell_id, step_log2, phase)
+            };
+            self.layers[layer].cache_next_gen(
+                cell_id.index(),
+                slowdown,
+                phase,
+                res.index(),
+                self.epoch,
+            );
             return res;
         }
 
-        let cell = self.get_cell(cell_id).unwrap().as_composite();
+        let cell = self.get_cell(cell_id).unwrap().into_composite();
 
         let nw = cell.nw;
         let ne = cell.ne;
         let sw = cell.sw;
         let se = cell.se;
 
-        let n00 = self.centered_subnode(nw);
-        let n01 = self.centered_horizontal(nw, ne);
-        let n02 = self.centered_subnode(ne);
-        let n10 = self.centered_vertical(nw, sw);
-        let n11 = self.centered_sub_subnode(nw, ne, sw, se);
-        let n12 = self.centered_vertical(ne, se);
-        let n20 = self.centered_subnode(sw);
-        let n21 = self.centered_horizontal(sw, se);
-        let n22 = self.centered_subnode(se);
+        let hyperspeed = step_log2 as usize == layer - self.step_offset;
+
+        let [n00, n01, n02, n10, n11, n12, n20, n21, n22] = if hyperspeed {
+            // Advance each of the nine overlapping sub-squares by half of the step
+            let n01 = self.horizontal(nw, ne);
+            let n10 = self.vertical(nw, sw);
+            let n11 = self.centered_subnode(cell_id);
+            let n12 = self.vertical(ne, se);
+            let n21 = self.horizontal(sw, se);
+
+            let squares = [nw, n01, ne, n10, n11, n12, sw, n21, se];
+            self.next_generations(squares, step_log2 - 1, phase, threads)
+        } else if layer - 3 < self.leaf_layer {
+            self.centered_leaves(cell_id)
+        } else {
+            [
+                self.centered_subnode(nw),
+                self.centered_horizontal(nw, ne),
+                self.centered_subnode(ne),
+                self.centered_vertical(nw, sw),
+                self.centered_sub_subnode(nw, ne, sw, se),
+                self.centered_vertical(ne, se),
+                self.centered_subnode(sw),
+                self.centered_horizontal(sw, se),
+                self.centered_subnode(se),
+            ]
+        };
+
+        let (remaining_log2, remaining_phase) = if hyperspeed {
+            (step_log2 - 1, self.phase_after(phase, 1 << (step_log2 - 1)))
+        } else {
+            (step_log2, phase)
+        };
 
         let nw = self.join(n00, n01, n10, n11);
         let ne = self.join(n01, n02, n11, n12);
         let sw = self.join(n10, n11, n20, n21);
         let se = self.join(n11, n12, n21, n22);
 
-        let nw = self.next_generation(nw);
-        let ne = self.next_generation(ne);
-        let sw = self.next_generation(sw);
-        let se = self.next_generation(se);
+        let [nw, ne, sw, se] =
+            self.next_generations([nw, ne, sw, se], remaining_log2, remaining_phase, threads);
 
         let res = self.join(nw, ne, sw, se);
 
         assert_eq!(res.layer(), cell_id.layer() - 1);
 
-        self.layers[cell_id.layer()].cache_next_gen(cell_id, res);
+        self.layers[layer].cache_next_gen(
+            cell_id.index(),
+            slowdown,
+            phase,
+            res.index(),
+            self.epoch,
+        );
         res
     }
 
-    // Takes 4 2x2 nodes and returns the new 2x2 center node
+    // Steps squares of the same layer, each worker thread taking every nth square. Any
+    // threads beyond one per square are shared out to step within the squares.
+    fn next_generations<const N: usize>(
+        &self,
+        squares: [CellId; N],
+        step_log2: u8,
+        phase: u8,
+        threads: usize,
+    ) -> [CellId; N] {
+        if threads <= 1 || squares[0].layer() < Self::MIN_THREADED_LAYER {
+            return squares.map(|square| self.next_generation(square, step_log2, phase));
+        }
+
+        let workers = threads.min(N);
+        let threads = threads.div_ceil(N);
+        let step = |worker: usize| {
+            (worker..N)
+                .step_by(workers)
+                .map(|i| {
+                    let res = self.next_generation_threaded(squares[i], step_log2, phase, threads);
+                    (i, res)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = (1..workers)
+                .map(|worker| scope.spawn(move || step(worker)))
+                .collect();
+
+            let mut res = squares;
+            let own = step(0);
+            let stepped = handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap());
+            for (i, id) in own.into_iter().chain(stepped) {
+                res[i] = id;
+            }
+            res
+        })
+    }
+
+    // Base case for two state rules on the 3x3 neighbourhood, stepping the square of
+    // four leaves a row at a time
+    fn next_generation_leaves(&self, cell_id: CellId, step_log2: u8, phase: u8) -> CellId {
+        let cell = self.get_cell(cell_id).unwrap().into_composite();
+        let [nw, ne, sw, se] =
+            [cell.nw, cell.ne, cell.sw, cell.se].map(|id| self.get_cell(id).unwrap().into_leaf());
+        let mut rows = LeafCell::square_rows([&nw, &ne, &sw, &se]);
+
+        // Each generation the rows which can still be stepped correctly shrink by one
+        // at either end
+        let width = rows.len();
+        let mut phase = phase;
+        for generation in 0..1 << step_log2 {
+            let rows = &mut rows[generation..width - generation];
+            match (&self.phase_count_rules, &self.phase_tables) {
+                (Some(count_rules), _) => count_rules[phase as usize].step_rows(rows),
+                (None, Some(tables)) => tables[phase as usize].step_rows(rows, width),
+                (None, None) => unreachable!("Leaves are only stepped by two state rules"),
+            }
+            phase = self.phase_after(phase, 1);
+        }
+
+        let offset = 1 << (self.leaf_layer - 1);
+        let leaf = LeafCell::from_rows(&rows, (offset, offset), self.leaf_layer);
+        self.add_cell(Cell::Leaf(leaf))
+    }
+
+    // Base case for any other rule, advancing the center of a cell at the base layer
+    // by reading out and stepping the states of all of its cells
+    fn next_generation_block(&self, cell_id: CellId, step_log2: u8, phase: u8) -> CellId {
+        let mut width = 1 << cell_id.layer();
+        let mut cells = vec![0; width * width];
+        self.read_states(cell_id, &mut cells, width, (0, 0));
+
+        let mut phase = phase;
+        for _ in 0..1 << step_log2 {
+            let rule = &self.phase_rules[phase as usize];
+            cells = rule.next_states(&cells, width);
+            width -= 2 * rule.radius() as usize;
+            phase = self.phase_after(phase, 1);
+        }
+
+        // Each generation only trims `radius` cells, which leaves at least the center
+        let offset = (width - (1 << (cell_id.layer() - 1))) / 2;
+        self.build_from_states(&cells, width, (offset, offset), cell_id.layer() - 1)
+    }
+
+    // Takes 4 2x2 nodes and returns the new 2x2 center node, with a single lookup for
+    // two state rules
     fn next_generation_base_case(
-        &mut self,
+        &self,
         nw: CellId,
         ne: CellId,
         sw: CellId,
         se: CellId,
+        phase: u8,
     ) -> CellId {
         debug_assert_eq!(nw.layer(), 1);
         debug_assert_eq!(ne.layer(), 1);
         debug_assert_eq!(sw.layer(), 1);
         debug_assert_eq!(se.layer(), 1);
 
-        let base_alive = self.base_alive;
-        let base_dead = self.base_dead;
-
-        let nw = self.get_cell(nw).unwrap().as_composite();
-        let ne = self.get_cell(ne).unwrap().as_composite();
-        let sw = self.get_cell(sw).unwrap().as_composite();
-        let se = self.get_cell(se).unwrap().as_composite();
+        let nw = self.get_cell(nw).unwrap().into_composite();
+        let ne = self.get_cell(ne).unwrap().into_composite();
+        let sw = self.get_cell(sw).unwrap().into_composite();
+        let se = self.get_cell(se).unwrap().into_composite();
 
         let cells = [
-            [
-                nw.nw.alive() > 0,
-                nw.ne.alive() > 0,
-                ne.nw.alive() > 0,
-                ne.ne.alive() > 0,
-            ],
-            [
-                nw.sw.alive() > 0,
-                nw.se.alive() > 0,
-                ne.sw.alive() > 0,
-                ne.se.alive() > 0,
-            ],
-            [
-                sw.nw.alive() > 0,
-                sw.ne.alive() > 0,
-                se.nw.alive() > 0,
-                se.ne.alive() > 0,
-            ],
-            [
-                sw.sw.alive() > 0,
-                sw.se.alive() > 0,
-                se.sw.alive() > 0,
-                se.se.alive() > 0,
-            ],
-        ];
+            [nw.nw, nw.ne, ne.nw, ne.ne],
+            [nw.sw, nw.se, ne.sw, ne.se],
+            [sw.nw, sw.ne, se.nw, se.ne],
+            [sw.sw, sw.se, se.sw, se.se],
+        ]
+        .map(|row| row.map(|cell_id| self.base_state(cell_id)));
 
-        let [nw, ne, sw, se] = [(1, 1), (2, 1), (1, 2), (2, 2)].map(|(x, y)| {
-            let alive = cells[y][x];
-
-            let directions: [(i32, i32); 8] = [
-                (-1, -1),
-                (0, -1),
-                (1, -1),
-                (-1, 0),
-                (1, 0),
-                (-1, 1),
-                (0, 1),
-                (1, 1),
-            ];
-
-            let surrounding: u32 = directions
-                .map(|(dx, dy)| cells[(y as i32 + dy) as usize][(x as i32 + dx) as usize] as u32)
+        if let Some(tables) = &self.phase_tables {
+            let block = cells
                 .iter()
-                .sum();
+                .flatten()
+                .rev()
+                .fold(0, |block, &state| block << 1 | (state == 1) as u16);
+            let center = tables[phase as usize].next_center(block);
+            let [nw, ne, sw, se] = [0, 1, 2, 3].map(|i| self.base_cell(center >> i & 1));
+            return self.join(nw, ne, sw, se);
+        }
 
-            if surrounding == 3 || alive && surrounding == 2 {
-                base_alive
-            } else {
-                base_dead
-            }
+        let [nw, ne, sw, se] = [(1, 1), (2, 1), (1, 2), (2, 2)].map(|(x, y)| {
+            // Read the 3x3 neighbourhood row by row from the north west
+            let neighbourhood: [u8; 9] =
+                std::array::from_fn(|i| cells[y + i / 3 - 1][x + i % 3 - 1]);
+            self.phase_rules[phase as usize].next_state(&neighbourhood)
         });
 
+        let [nw, ne, sw, se] = [nw, ne, sw, se].map(|state| self.base_cell(state));
         self.join(nw, ne, sw, se)
     }
 }
 
+impl Default for Life {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use pack_unpack::{test::assert_same_cells, CellBounds};
+    use std::collections::HashMap;
+
+    // Reference for the engine: the cells differing from empty space, stepped one
+    // generation at a time by applying the rule to the whole neighbourhood of each cell
+    #[derive(PartialEq, Debug)]
+    pub struct Naive {
+        cells: HashMap<(i64, i64), u8>,
+        background: u8,
+        // Cells outside the grid of a torus wrap around to the opposite edge
+        torus: Option<CellBounds>,
+    }
+
+    impl Naive {
+        pub fn of(life: &Life) -> Self {
+            let torus = match life.topology() {
+                Topology::Infinite => None,
+                Topology::Torus { .. } => life.grid_bounds(),
+                topology => panic!("No naive simulation on {topology}"),
+            };
+            Naive {
+                cells: life.cell_states(CellBounds::ALL).into_iter().collect(),
+                background: life.background_state(),
+                torus,
+            }
+        }
+
+        pub fn step(&mut self, rule: &Rule) {
+            let radius = rule.radius() as i64;
+            let bounds = self.torus.unwrap_or_else(|| CellBounds {
+                min_x: self.cells.keys().map(|&(x, _)| x).min().unwrap_or(0) - radius,
+                min_y: self.cells.keys().map(|&(_, y)| y).min().unwrap_or(0) - radius,
+                max_x: self.cells.keys().map(|&(x, _)| x).max().unwrap_or(0) + radius,
+                max_y: self.cells.keys().map(|&(_, y)| y).max().unwrap_or(0) + radius,
+            });
+
+            let state = |x: i64, y: i64| {
+                let (x, y) = match self.torus {
+                    Some(grid) => (
+                        (x - grid.min_x).rem_euclid(grid.max_x - grid.min_x + 1) + grid.min_x,
+                        (y - grid.min_y).rem_euclid(grid.max_y - grid.min_y + 1) + grid.min_y,
+                    ),
+                    None => (x, y),
+                };
+                self.cells
+                    .get(&(x, y))
+                    .map_or(self.background, |state| state ^ self.background)
+            };
+
+            // The rule steps squares, so the bounds are read as one with margins around it
+            let size = (bounds.max_x - bounds.min_x).max(bounds.max_y - bounds.min_y) + 1;
+            let width = size + 2 * radius;
+            let square: Vec<u8> = (0..width * width)
+                .map(|i| {
+                    state(
+                        bounds.min_x - radius + i % width,
+                        bounds.min_y - radius + i / width,
+                    )
+                })
+                .collect();
+            let width = (2 * radius + 1) as usize;
+            let background = rule.next_states(&vec![self.background; width * width], width)[0];
+
+            self.cells = rule
+                .next_states(&square, (size + 2 * radius) as usize)
+                .into_iter()
+                .enumerate()
+                .map(|(i, state)| {
+                    let (x, y) = (
+                        bounds.min_x + i as i64 % size,
+                        bounds.min_y + i as i64 / size,
+                    );
+                    ((x, y), state ^ background)
+                })
+                .filter(|&((x, y), state)| state != 0 && x <= bounds.max_x && y <= bounds.max_y)
+                .collect();
+            self.background = background;
+        }
+    }
+
+    // Steps the universe by each number of generations in turn, comparing it with the
+    // naive simulation after each
+    #[track_caller]
+    pub fn assert_steps_match_naive(life: &mut Life, steps: &[u128]) {
+        let mut expected = Naive::of(life);
+        for &generations in steps {
+            life.step_by(generations).unwrap();
+            for _ in 0..generations {
+                expected.step(life.rule());
+            }
+            assert_eq!(
+                Naive::of(life),
+                expected,
+                "{} at generation {}",
+                life.rule(),
+                life.generation()
+            );
+        }
+    }
+
+    pub fn r_pentomino() -> Life {
+        Life::from_cell_positions(vec![(1, 0), (2, 0), (0, 1), (1, 1), (1, 2)])
+    }
 
     #[test]
     fn test_base_cell_can_be_retrieved_via_id() {
-        let mut life = Life::new(8);
+        let life = Life::new();
 
-        let cell_alive = Cell::Base(cell::BaseCell::Alive);
-        let cell_dead = Cell::Base(cell::BaseCell::Dead);
+        let cell_alive = Cell::Base(BaseCell::ALIVE);
+        let cell_dead = Cell::Base(BaseCell::DEAD);
 
         let alive_id = life.add_cell(cell_alive.clone());
         let dead_id = life.add_cell(cell_dead.clone());
@@ -255,13 +965,13 @@ mod tests {
         let alive = life.get_cell(alive_id).unwrap();
         let dead = life.get_cell(dead_id).unwrap();
 
-        assert_eq!(alive, &cell_alive);
-        assert_eq!(dead, &cell_dead);
+        assert_eq!(alive, cell_alive);
+        assert_eq!(dead, cell_dead);
     }
 
     #[test]
     fn empty_of_layer_works() {
-        let mut life = Life::new(8);
+        let mut life = Life::new();
 
         let empty_0 = life.empty_of_layer(0);
         let empty_1 = life.empty_of_layer(1);
@@ -274,7 +984,7 @@ mod tests {
 
     #[test]
     fn padding_works() {
-        let mut life = Life::new(8);
+        let mut life = Life::new();
 
         let empty = life.empty_of_layer(0);
         assert_eq!(empty.layer(), 0);
@@ -283,4 +993,270 @@ mod tests {
         let padded_2 = life.padded(padded_1);
         assert_eq!(padded_2.layer(), 2);
     }
+
+    #[test]
+    fn step_by_matches_naive_simulation() {
+        assert_steps_match_naive(&mut r_pentomino(), &[1, 2, 3, 8, 13, 64, 100]);
+    }
+
+    #[test]
+    fn generation_round_trips_through_rle() {
+        let mut life = r_pentomino();
+        life.step_by(1103).unwrap();
+        assert_eq!(life.generation(), 1103);
+
+        let imported = Life::from_rle(&life.to_rle());
+        assert_eq!(imported.generation(), 1103);
+        assert_eq!(imported.root.alive(), life.root.alive());
+    }
+
+    #[test]
+    fn garbage_collection_keeps_reachable_cells() {
+        let mut life = r_pentomino();
+        life.set_gc_threshold(None);
+        life.step_by(500).unwrap();
+
+        let mut pinned = [life.root];
+        let pinned_centre = life.root_centre;
+        let pinned_positions = life.cell_positions(0, CellBounds::ALL);
+
+        life.step_by(500).unwrap();
+        let mut collected = life.clone();
+        let before = collected.node_count();
+        collected.collect_garbage(&mut pinned);
+        assert!(collected.node_count() < before);
+
+        life.step_by(500).unwrap();
+        collected.step_by(500).unwrap();
+        assert_eq!(
+            collected.cell_positions(0, CellBounds::ALL),
+            life.cell_positions(0, CellBounds::ALL)
+        );
+
+        collected.root = pinned[0];
+        collected.root_centre = pinned_centre;
+        assert_eq!(
+            collected.cell_positions(0, CellBounds::ALL),
+            pinned_positions
+        );
+    }
+
+    #[test]
+    fn gc_threshold_is_kept_while_collections_back_off() {
+        let mut life = r_pentomino();
+        life.set_gc_threshold(Some(100));
+        for _ in 0..300 {
+            life.step().unwrap();
+        }
+        assert_eq!(life.gc_threshold, Some(100));
+        assert!(life.next_gc_at > 100);
+
+        let extracted = life.extract(CellBounds::ALL);
+        assert_eq!(extracted.gc_threshold, Some(100));
+        assert_eq!(extracted.next_gc_at, 100);
+    }
+
+    #[test]
+    fn memory_budget_evicts_old_results() {
+        let mut unlimited = r_pentomino();
+        unlimited.set_gc_threshold(None);
+        let mut budgeted = unlimited.clone();
+        for _ in 0..600 {
+            unlimited.step().unwrap();
+        }
+
+        budgeted.set_memory_budget(Some(unlimited.memory_usage() / 4));
+        for _ in 0..600 {
+            budgeted.step().unwrap();
+        }
+        assert_eq!(
+            budgeted.cell_positions(0, CellBounds::ALL),
+            unlimited.cell_positions(0, CellBounds::ALL)
+        );
+        assert!(budgeted.memory_usage() < unlimited.memory_usage());
+
+        let stats = budgeted.eviction_stats();
+        assert!(stats.evicted_results > 0 && stats.collected_nodes > 0);
+        assert!(stats.recomputed_results > 0);
+        assert_eq!(unlimited.eviction_stats(), EvictionStats::default());
+    }
+
+    #[test]
+    fn memory_budget_below_the_live_set_backs_off() {
+        let mut unlimited = r_pentomino();
+        unlimited.set_gc_threshold(None);
+        let mut budgeted = unlimited.clone();
+        budgeted.set_memory_budget(Some(1));
+
+        let mut evictions = 0;
+        for _ in 0..600 {
+            let before = budgeted.eviction_stats().evicted_results;
+            budgeted.step().unwrap();
+            unlimited.step().unwrap();
+            evictions += (budgeted.eviction_stats().evicted_results > before) as usize;
+        }
+        assert_eq!(
+            budgeted.cell_positions(0, CellBounds::ALL),
+            unlimited.cell_positions(0, CellBounds::ALL)
+        );
+        assert!(evictions > 0 && evictions < 60, "{evictions} evictions");
+        assert!(budgeted.next_eviction_at > 1);
+    }
+
+    #[test]
+    fn rules_change_only_while_empty_space_keeps_its_state() {
+        let mut life = r_pentomino();
+        life.set_rule("B036/S125".parse().unwrap()).unwrap();
+        life.step().unwrap();
+        let displayed = |life: &Life| -> Vec<u8> {
+            (-20..20)
+                .flat_map(|y| (-20..20).map(move |x| (x, y)))
+                .map(|(x, y)| life.get_cell_at(x, y))
+                .collect()
+        };
+        let before = displayed(&life);
+
+        // Empty space is alive on odd generations, which Conway's Life can't show
+        assert_eq!(life.set_rule(Rule::CONWAY), Err(UnboundedEdit));
+        assert_eq!(life.rule().to_string(), "B036/S125");
+
+        // Another rule alternating the same way keeps every displayed cell
+        life.set_rule("B01/S1".parse().unwrap()).unwrap();
+        assert_eq!(displayed(&life), before);
+
+        life.step().unwrap();
+        let before = displayed(&life);
+        life.set_rule(Rule::CONWAY).unwrap();
+        assert_eq!(displayed(&life), before);
+    }
+
+    #[test]
+    fn b0_rules_match_naive_simulation() {
+        // The first rule has S8, so the background stays alive after the first generation
+        for rule in ["B0123478/S01234678", "B036/S125", "B0/S2H"] {
+            let mut life = r_pentomino();
+            life.set_rule(rule.parse().unwrap()).unwrap();
+            assert_steps_match_naive(&mut life, &[1, 1, 2, 4, 1, 8, 16, 3]);
+        }
+    }
+
+    #[test]
+    fn multi_state_cells_round_trip_through_rle() {
+        let rle = "x = 5, y = 2, rule = /2/30\nA.B$pAC2.pD!\n";
+        let life = Life::from_rle(rle);
+        let exported = life.to_rle();
+        assert!(exported.contains("rule = /2/30"));
+        assert!(exported.contains("A.B$pAC2.pD!"));
+
+        let imported = Life::from_rle(&exported);
+        assert_eq!(
+            imported.cell_states(CellBounds::ALL),
+            life.cell_states(CellBounds::ALL)
+        );
+    }
+
+    // Scatters live cells over a square, deterministically
+    pub fn soup(size: i64) -> Vec<(i64, i64)> {
+        let mut seed: u64 = 12345;
+        (0..size * size)
+            .filter(|_| {
+                seed = seed
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                seed >> 63 == 0
+            })
+            .map(|i| (i % size, i / size))
+            .collect()
+    }
+
+    #[test]
+    fn threads_give_the_same_results() {
+        for rule in ["B3/S23", "B0123478/S01234678", "/2/3"] {
+            let mut sequential = Life::from_cell_positions(soup(24));
+            sequential.set_rule(rule.parse().unwrap()).unwrap();
+            sequential.step_by(300).unwrap();
+
+            for threads in [3, 8] {
+                let mut life = Life::from_cell_positions(soup(24));
+                life.set_rule(rule.parse().unwrap()).unwrap();
+                life.set_threads(threads);
+                for generations in [1, 7, 128, 164] {
+                    life.step_by(generations).unwrap();
+                }
+                assert_same_cells(&life, &sequential);
+            }
+        }
+    }
+
+    #[test]
+    fn trimming_follows_the_pattern() {
+        let glider = vec![(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+        let mut life = Life::from_cell_positions(glider.clone());
+        life.step_by(4000).unwrap();
+
+        // The glider moves one cell diagonally every four generations
+        let moved = glider.iter().map(|&(x, y)| (x + 1000, y + 1000)).collect();
+        assert_same_cells(&life, &Life::from_cell_positions(moved));
+        assert_eq!(life.root.layer(), life.leaf_layer + 2);
+
+        // A pair of cells dies out
+        let mut life = Life::from_cell_positions(vec![(0, 0), (1, 0)]);
+        life.set_leaf_size(1);
+        life.step().unwrap();
+        assert_eq!(life.root.alive(), 0);
+        assert_eq!(life.root.layer(), 2);
+    }
+
+    #[test]
+    fn steps_past_the_range_of_coordinates() {
+        // A block stays put, so it can be stepped by any number of generations
+        let block = vec![(0, 0), (1, 0), (0, 1), (1, 1)];
+        let mut life = Life::from_cell_positions(block.clone());
+        life.step_by((1 << 64) + 3).unwrap();
+        assert_eq!(life.generation(), (1 << 64) + 3);
+        assert_same_cells(&life, &Life::from_cell_positions(block));
+
+        // A glider moves 2^62 cells in 2^64 generations, which still fits
+        let glider = vec![(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+        let mut life = Life::from_cell_positions(glider.clone());
+        life.step_by(1 << 64).unwrap();
+        let moved = glider
+            .iter()
+            .map(|&(x, y)| (x + (1 << 62), y + (1 << 62)))
+            .collect();
+        assert_same_cells(&life, &Life::from_cell_positions(moved));
+
+        // but runs out of coordinates before 2^66, and is left where it got to
+        assert_eq!(life.step_by(1 << 66), Err(CoordinateOverflow));
+        assert!(life.generation() > 1 << 64 && life.generation() < 1 << 66);
+        let distance = (life.generation() / 4) as i64;
+        let moved = glider
+            .iter()
+            .map(|&(x, y)| (x + distance, y + distance))
+            .collect();
+        assert_same_cells(&life, &Life::from_cell_positions(moved));
+    }
+
+    #[test]
+    fn layers_grow_on_demand() {
+        let mut life = r_pentomino();
+        let layers = life.layers.len();
+
+        life.step_by(1 << 40).unwrap();
+        assert!(life.layers.len() > layers);
+        // The root is trimmed after the step, but had to be stepped from above layer 40
+        assert!(life.layers.len() > 41);
+    }
+
+    #[test]
+    fn step_by_is_independent_of_step_size() {
+        let mut single = r_pentomino();
+        let mut jumped = r_pentomino();
+
+        for _ in 0..300 {
+            single.step().unwrap();
+        }
+        jumped.advance_to(300).unwrap();
+        assert_same_cells(&jumped, &single);
+    }
 }
@@ -0,0 +1,262 @@
+// Set operations between universes, combining the two trees a pair of cells at a time.
+// Results are memoised on the pair, and empty or identical cells are settled without
+// descending, so the parts two universes share cost next to nothing.
+
+use std::collections::HashMap;
+
+use super::{cell::Cell, cell_id::CellId, pack_unpack::CellBounds, region::UnboundedEdit, Life};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(super) enum Operation {
+    Or,
+    And,
+    Xor,
+    AndNot,
+}
+
+impl Operation {
+    // Where both cells are alive, the union takes the state of the second and the
+    // intersection that of the first
+    fn states(self, state: u8, other: u8) -> u8 {
+        match self {
+            Operation::Or if other != 0 => other,
+            Operation::And if other == 0 => 0,
+            Operation::Xor if state == 0 => other,
+            Operation::Xor | Operation::AndNot if other != 0 => 0,
+            _ => state,
+        }
+    }
+
+    fn rows(self, row: u16, other: u16) -> u16 {
+        match self {
+            Operation::Or => row | other,
+            Operation::And => row & other,
+            Operation::Xor => row ^ other,
+            Operation::AndNot => row & !other,
+        }
+    }
+
+    // The state of empty space after combining universes with the given backgrounds.
+    pub(super) fn background(self, (background, other_background): (u8, u8)) -> u8 {
+        self.rows(background as u16, other_background as u16) as u8 & 1
+    }
+
+    // The operation on stored cells which has the effect of this one on displayed cells,
+    // storing the result against its own background, and whether the two cells are taken
+    // the other way round. Empty space stays empty, so one always exists.
+    pub(super) fn on_stored(self, backgrounds: (u8, u8)) -> (Operation, bool) {
+        let (background, other_background) = backgrounds;
+        let result = self.background(backgrounds);
+        let stored = |state: u16, other: u16| {
+            self.rows(state ^ background as u16, other ^ other_background as u16) & 1
+                ^ result as u16
+        };
+        match (stored(0, 1), stored(1, 0), stored(1, 1)) {
+            (1, 1, 1) => (Operation::Or, false),
+            (0, 0, 1) => (Operation::And, false),
+            (1, 1, 0) => (Operation::Xor, false),
+            (0, 1, 0) => (Operation::AndNot, false),
+            (1, 0, 0) => (Operation::AndNot, true),
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Life {
+    /// Cells alive in either universe, with the state of `other` where both are.
+    /// Like the other set operations, the result has the rule and generation of this
+    /// universe, and cells are compared as displayed. Under B0 rules this fails when
+    /// the operation would leave empty space unlike that of this universe.
+    pub fn union(&self, other: &Life) -> Result<Life, UnboundedEdit> {
+        self.combined_with(other, Operation::Or)
+    }
+
+    /// Cells alive in both universes.
+    pub fn intersection(&self, other: &Life) -> Result<Life, UnboundedEdit> {
+        self.combined_with(other, Operation::And)
+    }
+
+    /// Cells alive in this universe but not in `other`.
+    pub fn difference(&self, other: &Life) -> Result<Life, UnboundedEdit> {
+        self.combined_with(other, Operation::AndNot)
+    }
+
+    /// Cells alive in exactly one of the universes.
+    pub fn symmetric_difference(&self, other: &Life) -> Result<Life, UnboundedEdit> {
+        self.combined_with(other, Operation::Xor)
+    }
+
+    fn combined_with(&self, other: &Life, operation: Operation) -> Result<Life, UnboundedEdit> {
+        let backgrounds = (self.background_state(), other.background_state());
+        if operation.background(backgrounds) != backgrounds.0 {
+            return Err(UnboundedEdit);
+        }
+
+        let mut life = self.extract(CellBounds::ALL);
+        life.combine(other, (0, 0), operation, backgrounds);
+        life.trim();
+        Ok(life)
+    }
+
+    // Combines two cells of the same layer
+    pub(super) fn combined(
+        &mut self,
+        cell_id: CellId,
+        other: CellId,
+        operation: Operation,
+        combined: &mut HashMap<(CellId, CellId), CellId>,
+    ) -> CellId {
+        match operation {
+            Operation::Or | Operation::Xor if cell_id.alive() == 0 => return other,
+            Operation::And if other.alive() == 0 => return other,
+            _ if cell_id.alive() == 0 || other.alive() == 0 => return cell_id,
+            Operation::Or | Operation::And if cell_id == other => return cell_id,
+            Operation::Xor | Operation::AndNot if cell_id == other => {
+                return self.empty_of_layer(cell_id.layer() as u8)
+            }
+            _ => (),
+        }
+        if let Some(&id) = combined.get(&(cell_id, other)) {
+            return id;
+        }
+
+        let res = match (
+            self.get_cell(cell_id).unwrap(),
+            self.get_cell(other).unwrap(),
+        ) {
+            (Cell::Base(cell), Cell::Base(other)) => {
+                self.base_cell(operation.states(cell.state(), other.state()))
+            }
+            (Cell::Leaf(leaf), Cell::Leaf(other)) => {
+                let leaf = leaf.combined(&other, |row, other| operation.rows(row, other));
+                self.add_cell(Cell::Leaf(leaf))
+            }
+            (cell, other) => {
+                let (cell, other) = (cell.into_composite(), other.into_composite());
+                let children = [
+                    (cell.nw, other.nw),
+                    (cell.ne, other.ne),
+                    (cell.sw, other.sw),
+                    (cell.se, other.se),
+                ];
+                let [nw, ne, sw, se] =
+                    children.map(|(child, other)| self.combined(child, other, operation, combined));
+                self.join(nw, ne, sw, se)
+            }
+        };
+        combined.insert((cell_id, other), res);
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn set_operations_match_hash_sets() {
+        let first: HashSet<_> = (-40..40)
+            .flat_map(|x: i64| (-40..40).map(move |y: i64| (x, y)))
+            .filter(|(x, y)| (x * 5 + y * y).rem_euclid(9) < 3)
+            .collect();
+        let second: HashSet<_> = (-10..70)
+            .flat_map(|x: i64| (-25..30).map(move |y: i64| (x, y)))
+            .filter(|(x, y)| (x * x + y * 3).rem_euclid(7) < 3)
+            .collect();
+        let a = Life::from_cell_positions(first.iter().copied().collect());
+        let b = Life::from_cell_positions(second.iter().copied().collect());
+
+        let cells = |life: Result<Life, UnboundedEdit>| {
+            life.unwrap()
+                .cell_positions(0, CellBounds::ALL)
+                .into_iter()
+                .collect::<HashSet<_>>()
+        };
+        assert_eq!(cells(a.union(&b)), &first | &second);
+        assert_eq!(cells(a.intersection(&b)), &first & &second);
+        assert_eq!(cells(a.difference(&b)), &first - &second);
+        assert_eq!(cells(a.symmetric_difference(&b)), &first ^ &second);
+
+        assert!(cells(a.intersection(&Life::new())).is_empty());
+        assert!(cells(a.symmetric_difference(&a)).is_empty());
+    }
+
+    #[test]
+    fn rotors_are_what_changes_over_a_period() {
+        // A blinker beside a block, of which only the blinker's ends change
+        let mut life = Life::from_cell_positions(vec![
+            (0, 0),
+            (1, 0),
+            (2, 0),
+            (10, 0),
+            (11, 0),
+            (10, 1),
+            (11, 1),
+        ]);
+        let before = life.clone();
+        life.step().unwrap();
+        let rotor = before.symmetric_difference(&life).unwrap();
+        let expected = HashSet::from([(0, 0), (2, 0), (1, -1), (1, 1)]);
+        assert_eq!(
+            HashSet::from_iter(rotor.cell_positions(0, CellBounds::ALL)),
+            expected
+        );
+
+        let stator = before.intersection(&life).unwrap();
+        let expected = HashSet::from([(1, 0), (10, 0), (11, 0), (10, 1), (11, 1)]);
+        assert_eq!(
+            HashSet::from_iter(stator.cell_positions(0, CellBounds::ALL)),
+            expected
+        );
+    }
+
+    #[test]
+    fn b0_oscillators_are_compared_as_displayed() {
+        // Generations either side of a change of phase, for rules where the background
+        // alternates and where it stays alive, and against a rule without B0
+        for (rule, other_rule, first, second) in [
+            ("B036/S125", "B036/S125", 4, 7),
+            ("B036/S125", "B036/S125", 7, 4),
+            ("B036/S125", "B036/S125", 3, 3),
+            ("B0123478/S01234678", "B0123478/S01234678", 1000, 0),
+            ("B0123478/S01234678", "B0123478/S01234678", 0, 1),
+            ("B3/S23", "B036/S125", 2, 1),
+        ] {
+            let r_pentomino = vec![(1, 0), (2, 0), (0, 1), (1, 1), (1, 2)];
+            let mut a = Life::from_cell_positions(r_pentomino.clone());
+            a.set_rule(rule.parse().unwrap()).unwrap();
+            a.step_by(first).unwrap();
+            let mut b = Life::from_cell_positions(r_pentomino);
+            b.set_rule(other_rule.parse().unwrap()).unwrap();
+            b.step_by(second).unwrap();
+
+            let results = [
+                (a.union(&b), Operation::Or),
+                (a.intersection(&b), Operation::And),
+                (a.difference(&b), Operation::AndNot),
+                (a.symmetric_difference(&b), Operation::Xor),
+            ];
+            for (result, operation) in results {
+                let backgrounds = (a.background_state(), b.background_state());
+                let Ok(result) = result else {
+                    assert_ne!(operation.background(backgrounds), backgrounds.0);
+                    continue;
+                };
+                assert_eq!(result.generation(), first);
+                assert_eq!(result.rule().to_string(), rule);
+                for x in -60..60 {
+                    for y in -60..60 {
+                        let (state, other) = (a.get_cell_at(x, y), b.get_cell_at(x, y));
+                        let expected = operation.rows(state as u16, other as u16) as u8 & 1;
+                        assert_eq!(
+                            result.get_cell_at(x, y),
+                            expected,
+                            "{operation:?} of {rule} at ({x}, {y})"
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
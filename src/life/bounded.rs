@@ -0,0 +1,166 @@
+// Stepping on finite grids, following Golly: before each generation the cells joined
+// to the grid's edges are copied into the ring around it, and afterwards everything
+// outside the grid is cleared
+
+use std::collections::HashMap;
+
+use super::{pack_unpack::CellBounds, CoordinateOverflow, Life};
+
+impl Life {
+    // The grid is centred on the origin, with odd sizes leaving the extra cell on the right
+    pub fn grid_bounds(&self) -> Option<CellBounds> {
+        let (width, height) = self.topology.size()?;
+        let (min_x, min_y) = (-(width as i64 / 2), -(height as i64 / 2));
+        Some(CellBounds {
+            min_x,
+            min_y,
+            max_x: min_x + width as i64 - 1,
+            max_y: min_y + height as i64 - 1,
+        })
+    }
+
+    pub(super) fn step_bounded(&mut self) -> Result<(), CoordinateOverflow> {
+        self.join_edges();
+        self.step_pow2(0)?;
+        self.clear_outside_grid();
+        Ok(())
+    }
+
+    fn join_edges(&mut self) {
+        let grid = self.grid_bounds().unwrap();
+        let (width, height) = (grid.max_x - grid.min_x + 1, grid.max_y - grid.min_y + 1);
+
+        // Rules with a range read cells up to `radius` away, so a band that deep is joined
+        let depth = self.rule.radius() as i64 - 1;
+        let edges = [
+            (grid.min_x, grid.min_y, grid.max_x, grid.min_y + depth),
+            (grid.min_x, grid.max_y - depth, grid.max_x, grid.max_y),
+            (grid.min_x, grid.min_y, grid.min_x + depth, grid.max_y),
+            (grid.max_x - depth, grid.min_y, grid.max_x, grid.max_y),
+        ];
+        let mut edge_states = HashMap::new();
+        for (min_x, min_y, max_x, max_y) in edges {
+            let bounds = CellBounds {
+                min_x: min_x.max(grid.min_x),
+                min_y: min_y.max(grid.min_y),
+                max_x: max_x.min(grid.max_x),
+                max_y: max_y.min(grid.max_y),
+            };
+            for ((x, y), state) in self.cell_states(bounds) {
+                if (bounds.min_x..=bounds.max_x).contains(&x)
+                    && (bounds.min_y..=bounds.max_y).contains(&y)
+                {
+                    edge_states.insert((x - grid.min_x, y - grid.min_y), state);
+                }
+            }
+        }
+
+        let ring = (-depth - 1..width + depth + 1)
+            .flat_map(|x| (1..=depth + 1).flat_map(move |d| [(x, -d), (x, height - 1 + d)]))
+            .chain(
+                (0..height)
+                    .flat_map(|y| (1..=depth + 1).flat_map(move |d| [(-d, y), (width - 1 + d, y)])),
+            );
+
+        let ring_states = ring
+            .filter_map(|(x, y)| {
+                let state = *edge_states.get(&self.topology.wrap(x, y)?)?;
+                Some(((x + grid.min_x, y + grid.min_y), state))
+            })
+            .collect();
+        self.set_stored_states(ring_states);
+    }
+
+    pub(super) fn clear_outside_grid(&mut self) {
+        let Some(grid) = self.grid_bounds() else {
+            return;
+        };
+        self.root = self.cleared(self.root, self.root_corner(), grid, false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::life::{pack_unpack::test::assert_same_cells, rule::Topology};
+
+    // Gliders moving down and to the right, and up and to the right
+    const SOUTH_EAST: &str = "bo$2bo$3o!";
+    const NORTH_EAST: &str = "3o$2bo$bo!";
+
+    fn glider(glider: &str, (x, y): (i64, i64), topology: &str) -> Life {
+        let rle = format!("#CXRLE Pos={x},{y}\nx = 3, y = 3, rule = B3/S23{topology}\n{glider}\n");
+        Life::from_rle(&rle)
+    }
+
+    #[track_caller]
+    fn assert_cells_after(life: &mut Life, generations: u128, cells: &[(i64, i64)]) {
+        life.step_by(generations).unwrap();
+        assert_same_cells(life, &Life::from_cell_positions(cells.to_vec()));
+    }
+
+    #[test]
+    fn topologies_are_read_from_the_rule_suffix() {
+        for suffix in [":P12,10", ":T12,10", ":K12*,10", ":K12,9*", ":S11"] {
+            let life = glider(SOUTH_EAST, (0, 0), suffix);
+            assert_eq!(life.topology().to_string(), suffix);
+            assert!(life.to_rle().contains(suffix));
+        }
+    }
+
+    #[test]
+    fn patterns_keep_their_position_in_the_grid() {
+        let life = glider(SOUTH_EAST, (-4, 1), ":T8,8");
+        let expected = [(-3, 1), (-2, 2), (-4, 3), (-3, 3), (-2, 3)];
+        assert_same_cells(&life, &Life::from_cell_positions(expected.to_vec()));
+
+        // The grid of 8 cells runs from -4 to 3
+        let rle = "#CXRLE Pos=2,0\nx = 3, y = 3, rule = B3/S23:T8,8\nbo$2bo$3o!\n";
+        assert!(Life::try_from_rle(rle).is_err());
+        let rle = "x = 9, y = 1, rule = B3/S23:T8,8\n9o!\n";
+        assert!(Life::try_from_rle(rle).is_err());
+    }
+
+    #[test]
+    fn gliders_wrap_around_a_torus() {
+        let mut life = glider(SOUTH_EAST, (1, 1), ":T8,8");
+        // A glider moves one cell diagonally every four generations
+        assert_cells_after(&mut life, 4, &[(3, 2), (-4, 3), (2, -4), (3, -4), (-4, -4)]);
+        assert_cells_after(&mut life, 28, &[(2, 1), (3, 2), (1, 3), (2, 3), (3, 3)]);
+
+        // Nothing comes back from beyond the edges of a plane
+        life.set_topology(Topology::Plane {
+            width: 8,
+            height: 8,
+        });
+        life.step_by(32).unwrap();
+        assert!(life.root.alive() < 5);
+    }
+
+    #[test]
+    fn gliders_are_mirrored_across_the_twisted_edges_of_a_klein_bottle() {
+        // Crossing the bottom edge comes back at the top, mirrored left to right
+        let mut life = glider(SOUTH_EAST, (-2, 1), ":K8*,8");
+        assert_cells_after(&mut life, 4, &[(0, 2), (1, 3), (0, -4), (-1, -4), (-2, -4)]);
+        assert_cells_after(
+            &mut life,
+            4,
+            &[(1, 3), (-3, -4), (-1, -3), (-2, -3), (-3, -3)],
+        );
+        // and moves down and to the left
+        assert_cells_after(
+            &mut life,
+            4,
+            &[(-3, -4), (-4, -3), (-2, -2), (-3, -2), (-4, -2)],
+        );
+    }
+
+    #[test]
+    fn gliders_turn_across_the_edges_of_a_sphere() {
+        // Crossing the top edge comes back through the left edge, turned a quarter
+        let mut life = glider(NORTH_EAST, (-2, -8), ":S16");
+        assert_cells_after(&mut life, 8, &[(-7, 0), (-7, 1), (-7, 2), (-8, 2), (1, -8)]);
+        // and moves down and to the right
+        assert_cells_after(&mut life, 8, &[(-5, 2), (-5, 3), (-5, 4), (-6, 4), (-7, 3)]);
+    }
+}
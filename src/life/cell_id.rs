@@ -1,23 +1,51 @@
-use std::hash::Hash;
+use std::{fmt, hash::Hash};
 
 #[derive(Hash, PartialEq, Eq, Clone, Copy, Debug)]
-#[repr(transparent)]
-struct CellIndex(u32);
+struct CellIndex {
+    layer: u8,
+    index: u32,
+}
 
 impl CellIndex {
-    pub fn new(layer: u8, index: usize) -> Self {
-        CellIndex(((layer as u32) << 24) | index as u32)
+    pub fn new(layer: usize, index: usize) -> Result<Self, CellIndexOverflow> {
+        let overflow = CellIndexOverflow { layer };
+        if index > CellId::MAX_INDEX {
+            return Err(overflow);
+        }
+        Ok(CellIndex {
+            layer: layer.try_into().map_err(|_| overflow)?,
+            index: index.try_into().map_err(|_| overflow)?,
+        })
     }
 
     pub fn index(&self) -> usize {
-        (self.0 & 0x00ff_ffff) as usize
+        self.index as usize
     }
 
     pub fn layer(&self) -> usize {
-        ((self.0 >> 24) & 0xff) as usize
+        self.layer as usize
+    }
+}
+
+// Returned when a layer runs out of indices, instead of handing out an aliased id
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct CellIndexOverflow {
+    pub layer: usize,
+}
+
+impl fmt::Display for CellIndexOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Layer {} cannot hold more than {} cells",
+            self.layer,
+            CellId::MAX_INDEX + 1
+        )
     }
 }
 
+impl std::error::Error for CellIndexOverflow {}
+
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub struct CellId {
     index: CellIndex,
@@ -31,11 +59,14 @@ impl Hash for CellId {
 }
 
 impl CellId {
-    pub fn new(layer: usize, index: usize, alive: usize) -> Self {
-        CellId {
-            index: CellIndex::new(layer as u8, index),
+    // The last u32 is left free to mark empty slots of the layer lookups
+    pub const MAX_INDEX: usize = u32::MAX as usize - 1;
+
+    pub fn new(layer: usize, index: usize, alive: usize) -> Result<Self, CellIndexOverflow> {
+        Ok(CellId {
+            index: CellIndex::new(layer, index)?,
             alive,
-        }
+        })
     }
 
     pub fn layer(&self) -> usize {
@@ -50,3 +81,24 @@ impl CellId {
         self.alive
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_overflow_is_an_error() {
+        let id = CellId::new(3, CellId::MAX_INDEX, 0).unwrap();
+        assert_eq!(id.index(), CellId::MAX_INDEX);
+        assert_eq!(id.layer(), 3);
+
+        assert_eq!(
+            CellId::new(3, CellId::MAX_INDEX + 1, 0),
+            Err(CellIndexOverflow { layer: 3 })
+        );
+        assert_eq!(
+            CellId::new(256, 0, 0),
+            Err(CellIndexOverflow { layer: 256 })
+        );
+    }
+}
@@ -0,0 +1,185 @@
+// Reading and writing single cells in place, rebuilding only the cells on the paths down
+// to the ones changed, so the rest of the tree and its cached steps are shared as before
+
+use super::{cell::Cell, cell_id::CellId, Life};
+
+impl Life {
+    /// State of the cell at (x, y), as displayed. Rules with B0 store cells inverted
+    /// on generations where empty space is alive, which is undone here.
+    pub fn get_cell_at(&self, x: i64, y: i64) -> u8 {
+        self.stored_state_at(x, y) ^ self.background_state()
+    }
+
+    /// Sets the state of the cell at (x, y), growing the universe if it lies outside.
+    pub fn set_cell_at(&mut self, x: i64, y: i64, state: u8) {
+        self.set_cells([((x, y), state)]);
+    }
+
+    /// Sets the state of every listed cell, with later writes to the same position
+    /// taking precedence. Each cell above the changed ones is only rebuilt once.
+    pub fn set_cells(&mut self, cells: impl IntoIterator<Item = ((i64, i64), u8)>) {
+        let background = self.background_state();
+        let cells = cells
+            .into_iter()
+            .map(|(position, state)| (position, state ^ background))
+            .collect();
+        self.set_stored_states(cells);
+    }
+
+    fn stored_state_at(&self, x: i64, y: i64) -> u8 {
+        let (mut min_x, mut min_y) = self.root_corner();
+        let width = 1 << self.root.layer();
+        if !(min_x..min_x + width).contains(&x) || !(min_y..min_y + width).contains(&y) {
+            return 0;
+        }
+
+        let mut cell_id = self.root;
+        while cell_id.alive() > 0 {
+            let cell = match self.get_cell(cell_id).unwrap() {
+                Cell::Base(cell) => return cell.state(),
+                Cell::Leaf(leaf) => return leaf.state((x - min_x) as usize, (y - min_y) as usize),
+                Cell::Composite(cell) => cell,
+            };
+
+            let half_width = 1 << (cell_id.layer() - 1);
+            let (east, south) = (x >= min_x + half_width, y >= min_y + half_width);
+            cell_id = [cell.nw, cell.ne, cell.sw, cell.se][south as usize * 2 + east as usize];
+            min_x += east as i64 * half_width;
+            min_y += south as i64 * half_width;
+        }
+        0
+    }
+
+    // Writes the states as they are stored, padding the root until it holds them all
+    pub(super) fn set_stored_states(&mut self, cells: Vec<((i64, i64), u8)>) {
+        if cells.is_empty() {
+            return;
+        }
+
+        let min_x = cells.iter().map(|((x, _), _)| *x).min().unwrap();
+        let min_y = cells.iter().map(|((_, y), _)| *y).min().unwrap();
+        let max_x = cells.iter().map(|((x, _), _)| *x).max().unwrap();
+        let max_y = cells.iter().map(|((_, y), _)| *y).max().unwrap();
+        self.pad_to_contain((min_x, min_y), (max_x, max_y));
+
+        self.root = self.with_states(self.root, self.root_corner(), &cells);
+    }
+
+    // Pads the root until it holds every cell from the first corner to the second
+    pub(super) fn pad_to_contain(
+        &mut self,
+        (min_x, min_y): (i64, i64),
+        (max_x, max_y): (i64, i64),
+    ) {
+        loop {
+            let (x, y) = self.root_corner();
+            let width = 1 << self.root.layer();
+            if x <= min_x && y <= min_y && max_x < x + width && max_y < y + width {
+                return;
+            }
+            self.root = self.padded(self.root);
+        }
+    }
+
+    // Positions are absolute, with (x, y) the north west corner of the cell
+    fn with_states(
+        &self,
+        cell_id: CellId,
+        (x, y): (i64, i64),
+        cells: &[((i64, i64), u8)],
+    ) -> CellId {
+        let Some(&(_, last_state)) = cells.last() else {
+            return cell_id;
+        };
+
+        let cell = match self.get_cell(cell_id).unwrap() {
+            Cell::Base(_) => return self.base_cell(last_state),
+            Cell::Leaf(leaf) => {
+                let leaf = cells.iter().fold(leaf, |leaf, &((cell_x, cell_y), state)| {
+                    leaf.with_state((cell_x - x) as usize, (cell_y - y) as usize, state)
+                });
+                return self.add_cell(Cell::Leaf(leaf));
+            }
+            Cell::Composite(cell) => cell,
+        };
+
+        let half_width = 1 << (cell_id.layer() - 1);
+        let mut quadrants: [Vec<_>; 4] = Default::default();
+        for &((cell_x, cell_y), state) in cells {
+            let quadrant =
+                (cell_y >= y + half_width) as usize * 2 + (cell_x >= x + half_width) as usize;
+            quadrants[quadrant].push(((cell_x, cell_y), state));
+        }
+
+        let children = [cell.nw, cell.ne, cell.sw, cell.se];
+        let [nw, ne, sw, se] = std::array::from_fn(|i| {
+            let corner = (
+                x + (i % 2) as i64 * half_width,
+                y + (i / 2) as i64 * half_width,
+            );
+            self.with_states(children[i], corner, &quadrants[i])
+        });
+        self.join(nw, ne, sw, se)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::life::pack_unpack::{test::assert_same_cells, CellBounds};
+    use std::collections::HashSet;
+
+    #[test]
+    fn cells_are_read_back_after_writing() {
+        let mut life = Life::new();
+        life.set_cell_at(3, -2, 1);
+        life.set_cells([((-1000, 70), 1), ((5, 5), 1), ((5, 5), 0), ((6, 5), 1)]);
+        assert_eq!(life.get_cell_at(3, -2), 1);
+        assert_eq!(life.get_cell_at(-1000, 70), 1);
+        assert_eq!(life.get_cell_at(5, 5), 0);
+        assert_eq!(life.get_cell_at(1 << 40, 0), 0);
+
+        let positions: HashSet<_> = life
+            .cell_positions(0, CellBounds::ALL)
+            .into_iter()
+            .collect();
+        assert_eq!(positions, HashSet::from([(3, -2), (-1000, 70), (6, 5)]));
+
+        // Single cells hold any state
+        life.set_rule("/2/3".parse().unwrap()).unwrap();
+        life.set_cell_at(6, 5, 2);
+        assert_eq!(life.get_cell_at(6, 5), 2);
+    }
+
+    #[test]
+    fn edits_step_like_a_fresh_universe() {
+        let r_pentomino = [(1, 0), (2, 0), (0, 1), (1, 1), (1, 2)];
+        let mut fresh = Life::from_cell_positions(r_pentomino.to_vec());
+        fresh.step_by(100).unwrap();
+
+        // Write a block first, which is then cleared
+        let mut edited = Life::from_cell_positions(vec![(0, 0), (1, 0), (0, 1), (1, 1)]);
+        edited.step_by(100).unwrap();
+        edited.set_cells([(0, 0), (1, 0), (0, 1), (1, 1)].map(|position| (position, 0)));
+        edited.set_cells(r_pentomino.map(|position| (position, 1)));
+        edited.step_by(100).unwrap();
+
+        assert_same_cells(&edited, &fresh);
+    }
+
+    #[test]
+    fn b0_rules_read_and_write_displayed_states() {
+        let mut life = Life::from_cell_positions(vec![(0, 0)]);
+        life.set_rule("B0123478/S01234678".parse().unwrap())
+            .unwrap();
+        life.step().unwrap();
+        assert_eq!(life.background_state(), 1);
+        assert_eq!(life.get_cell_at(100, 100), 1);
+
+        life.set_cell_at(100, 100, 0);
+        assert_eq!(life.get_cell_at(100, 100), 0);
+        assert!(life
+            .cell_positions(0, CellBounds::ALL)
+            .contains(&(100, 100)));
+    }
+}
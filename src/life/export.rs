@@ -0,0 +1,106 @@
+// Utilities convert from hash-life to standard life representations
+
+use super::{
+    pack_unpack::CellBounds,
+    rule::{Rule, Topology},
+    Life,
+};
+
+const MAX_LINE_LENGTH: usize = 70;
+
+impl Life {
+    pub fn to_rle(&self) -> String {
+        let cells = self.cell_states(CellBounds::ALL);
+        cell_states_to_rle(cells, self.generation(), self.rule(), self.topology())
+    }
+}
+
+pub fn cell_positions_to_rle(
+    cells: Vec<(i64, i64)>,
+    generation: u128,
+    rule: &Rule,
+    topology: Topology,
+) -> String {
+    let cells = cells.into_iter().map(|position| (position, 1)).collect();
+    cell_states_to_rle(cells, generation, rule, topology)
+}
+
+// Two state rules use b and o, multi-state rules use ., A, B, ..., pA, ...
+fn state_tag(state: u8, multi_state: bool) -> String {
+    match (state, multi_state) {
+        (0, false) => "b".to_string(),
+        (_, false) => "o".to_string(),
+        (0, true) => ".".to_string(),
+        (state, true) => {
+            let prefix = match (state - 1) / 24 {
+                0 => String::new(),
+                prefix => char::from(b'p' + prefix - 1).to_string(),
+            };
+            prefix + &char::from(b'A' + (state - 1) % 24).to_string()
+        }
+    }
+}
+
+pub fn cell_states_to_rle(
+    mut cells: Vec<((i64, i64), u8)>,
+    generation: u128,
+    rule: &Rule,
+    topology: Topology,
+) -> String {
+    cells.retain(|(_, state)| *state != 0);
+    cells.sort_by_key(|&((x, y), _)| (y, x));
+    cells.dedup_by_key(|(position, _)| *position);
+
+    let multi_state = rule.states() > 2;
+
+    let min_x = cells.iter().map(|((x, _), _)| *x).min().unwrap_or(0);
+    let min_y = cells.iter().map(|((_, y), _)| *y).min().unwrap_or(0);
+    let max_x = cells.iter().map(|((x, _), _)| *x).max().unwrap_or(-1);
+    let max_y = cells.iter().map(|((_, y), _)| *y).max().unwrap_or(-1);
+
+    let mut rle = format!("#CXRLE Pos={min_x},{min_y} Gen={generation}\n");
+    rle += &format!(
+        "x = {}, y = {}, rule = {rule}{topology}\n",
+        max_x - min_x + 1,
+        max_y - min_y + 1
+    );
+
+    let mut runs: Vec<(i64, String)> = vec![];
+    let (mut x, mut y) = (min_x, min_y);
+
+    for ((cell_x, cell_y), state) in cells {
+        if cell_y > y {
+            runs.push((cell_y - y, "$".to_string()));
+            (x, y) = (min_x, cell_y);
+        }
+        if cell_x > x {
+            runs.push((cell_x - x, state_tag(0, multi_state)));
+        }
+
+        let tag = state_tag(state, multi_state);
+        match runs.last_mut() {
+            Some((length, last)) if cell_x == x && *last == tag => *length += 1,
+            _ => runs.push((1, tag)),
+        }
+        x = cell_x + 1;
+    }
+    runs.push((1, "!".to_string()));
+
+    let mut line = String::new();
+    for (length, tag) in runs {
+        let run = match length {
+            1 => tag,
+            _ => format!("{length}{tag}"),
+        };
+        if line.len() + run.len() > MAX_LINE_LENGTH {
+            rle += &line;
+            rle.push('\n');
+            line.clear();
+        }
+        line += &run;
+    }
+    rle += &line;
+    rle.push('\n');
+
+    rle
+}
@@ -0,0 +1,165 @@
+use super::{cell::Cell, cell_id::CellId, Life};
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct EvictionStats {
+    /// Cached next generations dropped to stay within the memory budget
+    pub evicted_results: usize,
+    /// Nodes collected after evicting results
+    pub collected_nodes: usize,
+    /// Next generations computed again for nodes whose results had been evicted
+    pub recomputed_results: usize,
+}
+
+// Results unused for this many steps are evicted in turn until memory fits the budget,
+// with 0 evicting every result
+const EVICTION_AGES: [u32; 4] = [16, 4, 1, 0];
+
+impl Life {
+    pub fn node_count(&self) -> usize {
+        self.layers.iter().map(|layer| layer.size()).sum()
+    }
+
+    /// Bytes allocated for the nodes of every layer and their cached results.
+    pub fn memory_usage(&self) -> usize {
+        self.layers.iter().map(|layer| layer.memory_usage()).sum()
+    }
+
+    /// Limits the bytes used by nodes and cached results, checked between steps. Cached
+    /// results which haven't been used for the longest are evicted first, then any nodes
+    /// only they kept alive. None lets memory grow without limit.
+    pub fn set_memory_budget(&mut self, budget: Option<usize>) {
+        self.memory_budget = budget;
+        self.next_eviction_at = budget.unwrap_or(usize::MAX);
+    }
+
+    pub fn eviction_stats(&self) -> EvictionStats {
+        EvictionStats {
+            recomputed_results: self.layers.iter().map(|layer| layer.recomputed()).sum(),
+            ..self.eviction_stats
+        }
+    }
+
+    // Only collects between steps, the ids of cells in flight during a step aren't tracked
+    pub(super) fn collect_garbage_if_needed(&mut self) {
+        self.evict_if_over_budget();
+
+        let Some(threshold) = self.gc_threshold else {
+            return;
+        };
+
+        if self.node_count() <= self.next_gc_at {
+            return;
+        }
+
+        self.collect_garbage(&mut []);
+
+        // Avoid collecting on every step when the live set alone is above the threshold
+        self.next_gc_at = threshold.max(self.node_count() * 2);
+    }
+
+    /// Removes every cell that is not reachable from the root, the given roots or the
+    /// cached next generation of a reachable cell.
+    ///
+    /// Cell ids are reassigned by the collection, so `extra_roots` is updated in place
+    /// and any other ids held on to by the caller become invalid.
+    pub fn collect_garbage(&mut self, extra_roots: &mut [CellId]) {
+        let mut live: Vec<Vec<bool>> = self
+            .layers
+            .iter()
+            .map(|layer| vec![false; layer.size()])
+            .collect();
+
+        let roots = [self.root, self.base_alive, self.base_dead];
+        for root in roots.iter().chain(extra_roots.iter()) {
+            self.mark(*root, &mut live);
+        }
+
+        // Cached results always live in a lower layer than the cell they belong to,
+        // so a single top down pass reaches every result of a live cell.
+        for layer in (0..self.layers.len()).rev() {
+            let results: Vec<usize> = self.layers[layer]
+                .next_gens_by_step()
+                .filter(|(index, _)| live[layer][*index])
+                .map(|(_, next_gen)| next_gen)
+                .collect();
+
+            for result in results {
+                self.mark(self.stored_id(layer - 1, result), &mut live);
+            }
+        }
+
+        let mut new_indices: Vec<Vec<Option<usize>>> = Vec::with_capacity(self.layers.len());
+        for (layer, live) in self.layers.iter_mut().zip(live.iter()) {
+            let below = new_indices.last().map_or(&[][..], Vec::as_slice);
+            let indices = layer.compact(live, below);
+            new_indices.push(indices);
+        }
+
+        self.root = remap_id(&new_indices, self.root);
+        self.base_alive = remap_id(&new_indices, self.base_alive);
+        self.base_dead = remap_id(&new_indices, self.base_dead);
+        for root in extra_roots.iter_mut() {
+            *root = remap_id(&new_indices, *root);
+        }
+    }
+
+    // Memory only shrinks once nodes are collected, so results are evicted oldest first
+    // until about the share of them that memory is over the budget by has gone, then
+    // collected in one pass. A pattern too large for the budget on its own is left as it
+    // is after evicting every result, until memory has grown to twice what it was.
+    fn evict_if_over_budget(&mut self) {
+        let Some(budget) = self.memory_budget else {
+            return;
+        };
+        let usage = self.memory_usage();
+        if usage <= self.next_eviction_at {
+            return;
+        }
+
+        let results: usize = self.layers.iter().map(|layer| layer.next_gen_count()).sum();
+        let excess = (results as u128 * (usage - budget) as u128 / usage as u128) as usize;
+        let mut evicted = 0;
+        for age in EVICTION_AGES {
+            let before = self.epoch.saturating_add(1).saturating_sub(age);
+            for layer in &mut self.layers {
+                evicted += layer.evict_next_gens(before);
+            }
+            if evicted >= excess {
+                break;
+            }
+        }
+        self.eviction_stats.evicted_results += evicted;
+
+        let nodes = self.node_count();
+        self.collect_garbage(&mut []);
+        self.eviction_stats.collected_nodes += nodes - self.node_count();
+
+        self.next_eviction_at = match self.memory_usage() > budget {
+            true => usage * 2,
+            false => budget,
+        };
+    }
+
+    fn mark(&self, root: CellId, live: &mut [Vec<bool>]) {
+        let mut stack = vec![root];
+
+        while let Some(cell_id) = stack.pop() {
+            let marked = &mut live[cell_id.layer()][cell_id.index()];
+            if *marked {
+                continue;
+            }
+            *marked = true;
+
+            if let Some(Cell::Composite(cell)) = self.get_cell(cell_id) {
+                stack.extend([cell.nw, cell.ne, cell.sw, cell.se]);
+                let next_gen = self.layers[cell_id.layer()].full_next_gen(cell_id.index());
+                stack.extend(next_gen.map(|index| self.stored_id(cell_id.layer() - 1, index)));
+            }
+        }
+    }
+}
+
+fn remap_id(new_indices: &[Vec<Option<usize>>], id: CellId) -> CellId {
+    let index = new_indices[id.layer()][id.index()].expect("Live cell references a collected cell");
+    CellId::new(id.layer(), index, id.alive()).expect("Compacting never grows a layer")
+}
@@ -1,6 +1,14 @@
 // Utilities convert from standard life representations to hash-life
 
 pub fn rle_to_cell_positions(rle: String, offset_x: i64, offset_y: i64) -> Vec<(i64, i64)> {
+    rle_to_cell_states(rle, offset_x, offset_y)
+        .into_iter()
+        .map(|(position, _)| position)
+        .collect()
+}
+
+// Reads both two state (b, o) and multi-state (., A, B, ..., pA, ...) cells
+pub fn rle_to_cell_states(rle: String, offset_x: i64, offset_y: i64) -> Vec<((i64, i64), u8)> {
     // Remove comments and size header
     let s: String = rle
         .lines()
@@ -17,21 +25,28 @@ pub fn rle_to_cell_positions(rle: String, offset_x: i64, offset_y: i64) -> Vec<(
 
     let mut run_length = 1;
     let mut run_length_chars = 0;
+    let mut state_prefix = 0;
 
     for c in body.chars() {
         match c {
-            'o' => {
-                for x in x..x + run_length {
-                    cells.push((x, y));
+            'o' | 'b' | '.' | 'A'..='X' => {
+                let state = match c {
+                    'o' => 1,
+                    'b' | '.' => 0,
+                    _ => state_prefix + (c as u32 - 'A' as u32) + 1,
+                };
+                if state != 0 {
+                    for x in x..x + run_length {
+                        cells.push(((x, y), state.min(u8::MAX as u32) as u8));
+                    }
                 }
                 x += run_length;
                 run_length_chars = 0;
                 run_length = 1;
+                state_prefix = 0;
             }
-            'b' => {
-                x += run_length;
-                run_length_chars = 0;
-                run_length = 1;
+            'p'..='y' => {
+                state_prefix = (c as u32 - 'p' as u32 + 1) * 24;
             }
             '$' => {
                 y += run_length;
@@ -39,6 +54,7 @@ pub fn rle_to_cell_positions(rle: String, offset_x: i64, offset_y: i64) -> Vec<(
                 x = offset_x;
                 run_length = 1;
             }
+            '!' => break,
             d if d.is_numeric() => {
                 let n = c.to_digit(10).unwrap();
                 if run_length_chars == 0 {
@@ -55,3 +71,35 @@ pub fn rle_to_cell_positions(rle: String, offset_x: i64, offset_y: i64) -> Vec<(
 
     cells
 }
+
+// Reads the generation from a Golly extended RLE header (#CXRLE Gen=...)
+pub fn rle_generation(rle: &str) -> u128 {
+    rle.lines()
+        .filter(|l| l.starts_with("#CXRLE"))
+        .flat_map(|l| l.split_whitespace())
+        .find_map(|field| field.strip_prefix("Gen="))
+        .and_then(|generation| generation.parse().ok())
+        .unwrap_or(0)
+}
+
+// Reads the position of the top left corner of the pattern from a Golly extended RLE
+// header (#CXRLE Pos=x,y), which is at the origin when missing
+pub fn rle_position(rle: &str) -> (i64, i64) {
+    rle.lines()
+        .filter(|l| l.starts_with("#CXRLE"))
+        .flat_map(|l| l.split_whitespace())
+        .find_map(|field| field.strip_prefix("Pos="))
+        .and_then(|position| {
+            let (x, y) = position.split_once(',')?;
+            Some((x.parse().ok()?, y.parse().ok()?))
+        })
+        .unwrap_or((0, 0))
+}
+
+// Reads the rule from the size header (x = 3, y = 3, rule = B3/S23). The rule is
+// the last field and may itself contain commas, as in B3/S23:T30,20
+pub fn rle_rule(rle: &str) -> Option<&str> {
+    let header = rle.lines().find(|l| !l.starts_with('#'))?;
+    let (_, rule) = header.split_once("rule")?;
+    Some(rule.trim().strip_prefix('=')?.trim())
+}
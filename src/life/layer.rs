@@ -1,47 +1,438 @@
-use std::collections::HashMap;
+mod lookup;
 
-use super::{cell::Cell, cell_id::CellId};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+        Mutex, OnceLock,
+    },
+};
 
-#[derive(Default, Clone, Debug)]
+use super::{cell::BaseCell, cell_id::CellId, leaf::LeafCell};
+use lookup::{hash_node, Lookup};
+
+// Cells can be added and results cached from several threads at once. Cells are kept in
+// chunks which never move once allocated, so they can be read without locking, while
+// the lookup of existing cells is split into shards locked independently.
+#[derive(Debug)]
 pub struct Layer {
-    pub calls: usize,
-    cells: Vec<Cell>,
-    cells_index_lookup: HashMap<Cell, usize>,
+    calls: AtomicUsize,
+    len: AtomicUsize,
+    // Chunk i holds the slots from FIRST_CHUNK * (2^i - 1), doubling in size each time
+    chunks: [OnceLock<Box<[Slot]>>; CHUNKS],
+    // Picked by the top bits of a cell's hash
+    shards: Box<[Mutex<Lookup>]>,
+    // Results of steps smaller than the full hyperspeed jump, or starting in a phase
+    // other than 0, keyed by cell index, slowdown and phase and sharded by cell index.
+    // The slowdown is the log2 of how many times smaller than the full jump the step is.
+    // Each result is stored with the epoch it was last used in.
+    next_gen_by_step: Shards<(u32, u8, u8), (u32, u32)>,
+    // Results cached again for cells whose results had been evicted
+    recomputed: AtomicUsize,
+}
+
+type Shards<K, V> = Box<[Mutex<HashMap<K, V>>]>;
+
+// A cell as it is stored. Children and results always live in the layer below, so only
+// their index is kept, and the population of each cell is only kept in its own slot.
+#[derive(Hash, PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Node {
+    Base(BaseCell),
+    Leaf(LeafCell),
+    Composite([u32; 4]),
+}
+
+#[derive(Debug)]
+struct Slot {
+    node: OnceLock<Node>,
+    alive: AtomicUsize,
+    // Index of the result of the full hyperspeed jump from phase 0, or NO_RESULT
+    next_gen: AtomicU32,
+    // Epoch in which the full jump was last used
+    last_used: AtomicU32,
+    // Whether any result of the cell has been evicted since it was last computed
+    evicted: AtomicBool,
+}
+
+// The last u32 is never handed out as an index, see `CellId::MAX_INDEX`
+const NO_RESULT: u32 = u32::MAX;
+
+impl Default for Slot {
+    fn default() -> Self {
+        Slot {
+            node: OnceLock::new(),
+            alive: AtomicUsize::new(0),
+            next_gen: AtomicU32::new(NO_RESULT),
+            last_used: AtomicU32::new(0),
+            evicted: AtomicBool::new(false),
+        }
+    }
+}
+
+impl Slot {
+    fn next_gen(&self) -> Option<usize> {
+        match self.next_gen.load(Ordering::Acquire) {
+            NO_RESULT => None,
+            index => Some(index as usize),
+        }
+    }
+
+    fn copy_results(&self, from: &Slot, remap: impl Fn(usize) -> usize) {
+        if let Some(next_gen) = from.next_gen() {
+            self.next_gen
+                .store(remap(next_gen) as u32, Ordering::Relaxed);
+        }
+        let last_used = from.last_used.load(Ordering::Relaxed);
+        self.last_used.store(last_used, Ordering::Relaxed);
+        let evicted = from.evicted.load(Ordering::Relaxed);
+        self.evicted.store(evicted, Ordering::Relaxed);
+    }
+}
+
+const FIRST_CHUNK_LOG2: u32 = 6;
+const CHUNKS: usize = usize::BITS as usize - FIRST_CHUNK_LOG2 as usize;
+const SHARD_BITS: u32 = 5;
+const SHARDS: usize = 1 << SHARD_BITS;
+
+impl Default for Layer {
+    fn default() -> Self {
+        Layer {
+            calls: AtomicUsize::new(0),
+            len: AtomicUsize::new(0),
+            chunks: std::array::from_fn(|_| OnceLock::new()),
+            shards: (0..SHARDS).map(|_| Mutex::default()).collect(),
+            next_gen_by_step: (0..SHARDS).map(|_| Mutex::default()).collect(),
+            recomputed: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Clone for Layer {
+    fn clone(&self) -> Self {
+        let layer = Layer::default();
+        for index in 0..self.size() {
+            let slot = self.slot(index).unwrap();
+            let node = *slot.node.get().unwrap();
+            layer.add_cell(node, self.alive(index)).unwrap();
+            layer.slot(index).unwrap().copy_results(slot, |index| index);
+        }
+        for (shard, cloned) in self
+            .next_gen_by_step
+            .iter()
+            .zip(layer.next_gen_by_step.iter())
+        {
+            *cloned.lock().unwrap() = shard.lock().unwrap().clone();
+        }
+        layer.calls.store(self.calls(), Ordering::Relaxed);
+        layer.recomputed.store(self.recomputed(), Ordering::Relaxed);
+        layer
+    }
 }
 
 impl Layer {
     pub fn size(&self) -> usize {
-        self.cells.len()
+        self.len.load(Ordering::Acquire)
+    }
+
+    // None once the layer has run out of indices, see `CellId::MAX_INDEX`
+    pub fn add_cell(&self, node: Node, alive: usize) -> Option<usize> {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        let hash = hash_node(&node);
+        let mut lookup = self.shards[(hash >> (u64::BITS - SHARD_BITS)) as usize]
+            .lock()
+            .unwrap();
+        let position = match lookup.find(hash, |index| self.get_cell(index) == Some(node)) {
+            Ok(index) => return Some(index),
+            Err(position) => position,
+        };
+
+        let cell_index = self.len.fetch_add(1, Ordering::AcqRel);
+        if cell_index > CellId::MAX_INDEX {
+            self.len.fetch_sub(1, Ordering::AcqRel);
+            return None;
+        }
+
+        let (chunk, offset) = Self::position(cell_index);
+        let slots = self.chunks[chunk].get_or_init(|| {
+            let size = 1 << (chunk as u32 + FIRST_CHUNK_LOG2);
+            (0..size).map(|_| Slot::default()).collect()
+        });
+        slots[offset].alive.store(alive, Ordering::Relaxed);
+        slots[offset].node.set(node).unwrap();
+        lookup.insert(position, cell_index, |index| {
+            hash_node(&self.get_cell(index).unwrap())
+        });
+
+        Some(cell_index)
+    }
+
+    pub fn get_cell(&self, index: usize) -> Option<Node> {
+        self.slot(index)?.node.get().copied()
+    }
+
+    pub fn alive(&self, index: usize) -> usize {
+        self.slot(index)
+            .map_or(0, |slot| slot.alive.load(Ordering::Relaxed))
     }
 
-    pub fn add_cell(&mut self, cell: Cell) -> usize {
-        self.calls += 1;
-        if let Some(index) = self.cells_index_lookup.get(&cell) {
-            return *index;
+    fn slot(&self, index: usize) -> Option<&Slot> {
+        let (chunk, offset) = Self::position(index);
+        self.chunks.get(chunk)?.get()?.get(offset)
+    }
+
+    // Chunk and offset within it of the slot holding a cell
+    fn position(index: usize) -> (usize, usize) {
+        let shifted = index + (1 << FIRST_CHUNK_LOG2);
+        let chunk_log2 = shifted.ilog2();
+        (
+            (chunk_log2 - FIRST_CHUNK_LOG2) as usize,
+            shifted - (1 << chunk_log2),
+        )
+    }
+
+    // Index in the layer below of the result, marking it as used in the given epoch
+    pub fn get_next_gen(&self, index: usize, slowdown: u8, phase: u8, epoch: u32) -> Option<usize> {
+        if Self::is_full_step(slowdown, phase) {
+            let slot = self.slot(index)?;
+            let next_gen = slot.next_gen()?;
+            slot.last_used.store(epoch, Ordering::Relaxed);
+            Some(next_gen)
+        } else {
+            let mut shard = self.next_gen_by_step[index % SHARDS].lock().unwrap();
+            let (next_gen, last_used) = shard.get_mut(&(index as u32, slowdown, phase))?;
+            *last_used = epoch;
+            Some(*next_gen as usize)
+        }
+    }
+
+    // Result of the full hyperspeed jump, without marking it as used
+    pub fn full_next_gen(&self, index: usize) -> Option<usize> {
+        self.slot(index)?.next_gen()
+    }
+
+    // Threads racing to step the same cell compute the same result, so whichever is
+    // cached first wins
+    pub fn cache_next_gen(
+        &self,
+        index: usize,
+        slowdown: u8,
+        phase: u8,
+        next_gen: usize,
+        epoch: u32,
+    ) {
+        let Some(slot) = self.slot(index) else {
+            return;
+        };
+        if slot.evicted.swap(false, Ordering::Relaxed) {
+            self.recomputed.fetch_add(1, Ordering::Relaxed);
+        }
+
+        if !Self::is_full_step(slowdown, phase) {
+            self.next_gen_by_step[index % SHARDS]
+                .lock()
+                .unwrap()
+                .insert((index as u32, slowdown, phase), (next_gen as u32, epoch));
+        } else {
+            let _ = slot.next_gen.compare_exchange(
+                NO_RESULT,
+                next_gen as u32,
+                Ordering::Release,
+                Ordering::Relaxed,
+            );
+            slot.last_used.store(epoch, Ordering::Relaxed);
+        }
+    }
+
+    pub fn next_gen_count(&self) -> usize {
+        let full: usize = self
+            .chunks
+            .iter()
+            .filter_map(OnceLock::get)
+            .map(|slots| {
+                slots
+                    .iter()
+                    .filter(|slot| slot.next_gen().is_some())
+                    .count()
+            })
+            .sum();
+        let by_step: usize = self
+            .next_gen_by_step
+            .iter()
+            .map(|shard| shard.lock().unwrap().len())
+            .sum();
+        full + by_step
+    }
+
+    // Drops every result last used before the given epoch, returning how many there were
+    pub fn evict_next_gens(&mut self, before: u32) -> usize {
+        let mut evicted = 0;
+        for slots in self.chunks.iter_mut().filter_map(OnceLock::get_mut) {
+            for slot in slots.iter_mut() {
+                if *slot.next_gen.get_mut() != NO_RESULT && *slot.last_used.get_mut() < before {
+                    *slot.next_gen.get_mut() = NO_RESULT;
+                    *slot.evicted.get_mut() = true;
+                    evicted += 1;
+                }
+            }
+        }
+
+        let mut evicted_indices = Vec::new();
+        for shard in self.next_gen_by_step.iter_mut() {
+            shard
+                .get_mut()
+                .unwrap()
+                .retain(|&(index, _, _), &mut (_, last_used)| {
+                    if last_used < before {
+                        evicted_indices.push(index as usize);
+                    }
+                    last_used >= before
+                });
+        }
+        for &index in &evicted_indices {
+            self.slot(index)
+                .unwrap()
+                .evicted
+                .store(true, Ordering::Relaxed);
         }
 
-        let cell_index = self.cells.len();
-        self.cells_index_lookup.insert(cell.clone(), cell_index);
-        self.cells.push(cell);
+        evicted + evicted_indices.len()
+    }
+
+    pub fn recomputed(&self) -> usize {
+        self.recomputed.load(Ordering::Relaxed)
+    }
 
-        cell_index
+    pub fn clear_next_gens(&mut self) {
+        for shard in self.next_gen_by_step.iter_mut() {
+            shard.get_mut().unwrap().clear();
+        }
+        for slots in self.chunks.iter_mut().filter_map(OnceLock::get_mut) {
+            for slot in slots.iter_mut() {
+                *slot.next_gen.get_mut() = NO_RESULT;
+            }
+        }
     }
 
-    pub fn get_cell(&self, index: usize) -> Option<&Cell> {
-        self.cells.get(index)
+    fn is_full_step(slowdown: u8, phase: u8) -> bool {
+        slowdown == 0 && phase == 0
     }
 
-    pub fn get_next_gen(&self, cell_id: CellId) -> Option<&CellId> {
-        self.cells[cell_id.index()].as_composite().next_gen.as_ref()
+    pub fn next_gens_by_step(&mut self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.next_gen_by_step.iter_mut().flat_map(|shard| {
+            shard
+                .get_mut()
+                .unwrap()
+                .iter()
+                .map(|(&(index, _, _), &(next_gen, _))| (index as usize, next_gen as usize))
+        })
     }
 
-    pub fn cache_next_gen(&mut self, cell_id: CellId, new_cell_id: CellId) {
-        if let Some(Cell::Composite(cell)) = self.cells.get_mut(cell_id.index()) {
-            cell.next_gen = Some(new_cell_id)
+    // Drops every cell that is not marked as live, returning the new index of each cell.
+    // `below` holds the new indices of the layer below, which must already be compacted.
+    pub fn compact(&mut self, live: &[bool], below: &[Option<usize>]) -> Vec<Option<usize>> {
+        let remap = |index: usize| below[index].expect("Live cell references a collected cell");
+        let old = std::mem::take(self);
+        self.calls.store(old.calls(), Ordering::Relaxed);
+        self.recomputed.store(old.recomputed(), Ordering::Relaxed);
+        let mut new_indices = vec![None; old.size()];
+
+        for (index, &live) in live.iter().enumerate() {
+            if !live {
+                continue;
+            }
+
+            let slot = old.slot(index).unwrap();
+            let node = match *slot.node.get().unwrap() {
+                Node::Composite(children) => {
+                    Node::Composite(children.map(|child| remap(child as usize) as u32))
+                }
+                node => node,
+            };
+
+            let new_index = self
+                .add_cell(node, old.alive(index))
+                .expect("Compacting never grows a layer");
+            self.slot(new_index).unwrap().copy_results(slot, remap);
+            new_indices[index] = Some(new_index);
+        }
+
+        for shard in old.next_gen_by_step.into_vec() {
+            for ((index, slowdown, phase), (next_gen, last_used)) in shard.into_inner().unwrap() {
+                if let Some(index) = new_indices[index as usize] {
+                    let next_gen = remap(next_gen as usize) as u32;
+                    self.next_gen_by_step[index % SHARDS]
+                        .get_mut()
+                        .unwrap()
+                        .insert((index as u32, slowdown, phase), (next_gen, last_used));
+                }
+            }
         }
+
+        new_indices
+    }
+
+    // Bytes allocated for the cells, their lookup and cached results
+    pub fn memory_usage(&self) -> usize {
+        let slots: usize = self
+            .chunks
+            .iter()
+            .filter_map(OnceLock::get)
+            .map(|slots| slots.len() * size_of::<Slot>())
+            .sum();
+        let lookup: usize = self
+            .shards
+            .iter()
+            .map(|shard| shard.lock().unwrap().capacity() * size_of::<u32>())
+            .sum();
+        let next_gens: usize = self
+            .next_gen_by_step
+            .iter()
+            .map(|shard| {
+                shard.lock().unwrap().capacity() * size_of::<((u32, u8, u8), (u32, u32))>()
+            })
+            .sum();
+        slots + lookup + next_gens
     }
 
     pub fn calls(&self) -> usize {
-        self.calls
+        self.calls.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cells_added_from_many_threads_are_stored_once() {
+        let layer = Layer::default();
+        let indices: Vec<Vec<usize>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..4)
+                .map(|_| {
+                    scope.spawn(|| {
+                        (0..=255)
+                            .map(|state| {
+                                let alive = (state != 0) as usize;
+                                layer.add_cell(Node::Base(BaseCell(state)), alive).unwrap()
+                            })
+                            .collect()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect()
+        });
+
+        assert_eq!(layer.size(), 256);
+        assert!(indices.iter().all(|thread| thread == &indices[0]));
+        for (state, &index) in indices[0].iter().enumerate() {
+            assert_eq!(
+                layer.get_cell(index),
+                Some(Node::Base(BaseCell(state as u8)))
+            );
+            assert_eq!(layer.alive(index), (state != 0) as usize);
+        }
     }
 }
@@ -0,0 +1,137 @@
+use std::hash::{Hash, Hasher};
+
+use super::Node;
+
+// Open addressing table of cell indices, probed linearly. Cells are only stored in the
+// layer, so finding a cell or growing the table reads them back through the closures
+// passed in.
+#[derive(Debug)]
+pub struct Lookup {
+    indices: Vec<u32>,
+    len: usize,
+}
+
+const EMPTY: u32 = u32::MAX;
+const INITIAL_CAPACITY: usize = 16;
+
+impl Default for Lookup {
+    fn default() -> Self {
+        Lookup {
+            indices: vec![EMPTY; INITIAL_CAPACITY],
+            len: 0,
+        }
+    }
+}
+
+impl Lookup {
+    pub fn capacity(&self) -> usize {
+        self.indices.len()
+    }
+
+    // Index of the cell with the given hash for which `matches` holds, or else the
+    // position to insert it at
+    pub fn find(&self, hash: u64, matches: impl Fn(usize) -> bool) -> Result<usize, usize> {
+        let mask = self.indices.len() - 1;
+        let mut position = hash as usize & mask;
+        loop {
+            match self.indices[position] {
+                EMPTY => return Err(position),
+                index if matches(index as usize) => return Ok(index as usize),
+                _ => position = (position + 1) & mask,
+            }
+        }
+    }
+
+    // Inserts an index at the position returned by `find`, growing the table to keep
+    // it at most half full
+    pub fn insert(&mut self, position: usize, index: usize, hash_of: impl Fn(usize) -> u64) {
+        debug_assert!(index < EMPTY as usize);
+        self.indices[position] = index as u32;
+        self.len += 1;
+        if self.len * 2 > self.indices.len() {
+            self.grow(hash_of);
+        }
+    }
+
+    // Doubles the table within its own buffer, placing each index again from its hash
+    fn grow(&mut self, hash_of: impl Fn(usize) -> u64) {
+        let capacity = self.indices.len() * 2;
+        let mut taken = Vec::with_capacity(self.len);
+        for index in self.indices.iter_mut().filter(|index| **index != EMPTY) {
+            taken.push(std::mem::replace(index, EMPTY));
+        }
+        self.indices.resize(capacity, EMPTY);
+
+        for index in taken {
+            let mut position = hash_of(index as usize) as usize & (capacity - 1);
+            while self.indices[position] != EMPTY {
+                position = (position + 1) & (capacity - 1);
+            }
+            self.indices[position] = index;
+        }
+    }
+}
+
+// Composite cells are keyed on the indices of their children, which all share a layer.
+// Anything else is fed word by word through the same folded multiply.
+pub fn hash_node(node: &Node) -> u64 {
+    if let Node::Composite(children) = node {
+        let [nw, ne, sw, se] = children.map(u64::from);
+        return fold(nw | ne << 32, sw | se << 32);
+    }
+
+    let mut hasher = FoldHasher(0);
+    node.hash(&mut hasher);
+    hasher.finish()
+}
+
+// Multiplies two words into 128 bits and folds the halves together, which mixes every
+// input bit into both the low bits picking table positions and the high bits picking
+// shards
+fn fold(a: u64, b: u64) -> u64 {
+    let product = (a ^ 0x243f_6a88_85a3_08d3) as u128 * (b ^ 0x1319_8a2e_0370_7344) as u128;
+    product as u64 ^ (product >> 64) as u64
+}
+
+struct FoldHasher(u64);
+
+impl Hasher for FoldHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for chunk in bytes.chunks(8) {
+            let mut word = [0; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            self.0 = fold(self.0, u64::from_le_bytes(word));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indices_are_found_after_growing() {
+        // Every index hashes to one of a few positions, so they collide and wrap around
+        let hash_of = |index: usize| (index % 3) as u64 * 7;
+        let mut lookup = Lookup::default();
+        for index in 0..1000 {
+            let position = lookup
+                .find(hash_of(index), |found| found == index)
+                .unwrap_err();
+            lookup.insert(position, index, hash_of);
+        }
+
+        assert!(lookup.capacity() >= 2000);
+        for index in 0..1000 {
+            assert_eq!(
+                lookup.find(hash_of(index), |found| found == index),
+                Ok(index)
+            );
+        }
+        assert!(lookup.find(hash_of(1000), |found| found == 1000).is_err());
+    }
+}
@@ -0,0 +1,416 @@
+use std::sync::Arc;
+
+use super::rule::{Neighbourhood, Rule};
+
+// Widest supported leaf, the rows of four leaves must fit in a u32 when stepping
+pub const MAX_LEAF_LAYER: usize = 4;
+
+// Square block of two state cells packed into bits, bit x of row y holding the cell at
+// (x, y). Leaves narrower than the maximum leave the high bits and rows unused.
+#[derive(Hash, PartialEq, Eq, Clone, Copy, Debug)]
+pub struct LeafCell {
+    layer: u8,
+    rows: [u16; 1 << MAX_LEAF_LAYER],
+}
+
+impl LeafCell {
+    pub fn empty(layer: usize) -> Self {
+        debug_assert!(layer <= MAX_LEAF_LAYER);
+        LeafCell {
+            layer: layer as u8,
+            rows: [0; 1 << MAX_LEAF_LAYER],
+        }
+    }
+
+    pub fn full(layer: usize) -> Self {
+        let mut leaf = LeafCell::empty(layer);
+        leaf.rows[..1 << layer].fill(((1u32 << (1 << layer)) - 1) as u16);
+        leaf
+    }
+
+    pub fn layer(&self) -> u8 {
+        self.layer
+    }
+
+    fn width(&self) -> usize {
+        1 << self.layer
+    }
+
+    pub fn alive_count(&self) -> usize {
+        self.rows.iter().map(|row| row.count_ones() as usize).sum()
+    }
+
+    pub fn state(&self, x: usize, y: usize) -> u8 {
+        (self.rows[y] >> x & 1) as u8
+    }
+
+    // Any state other than dead is stored as alive
+    pub fn with_state(mut self, x: usize, y: usize, state: u8) -> Self {
+        self.rows[y] = self.rows[y] & !(1 << x) | ((state != 0) as u16) << x;
+        self
+    }
+
+    // Whether any cell of the square of the given width at (x, y) is alive
+    pub fn any_alive(&self, x: usize, y: usize, width: usize) -> bool {
+        let mask = ((1u32 << width) - 1) << x;
+        self.rows[y..y + width]
+            .iter()
+            .any(|&row| row as u32 & mask != 0)
+    }
+
+    // Clears every cell outside the given inclusive range of columns and rows
+    pub fn cleared_outside(
+        mut self,
+        (min_x, max_x): (usize, usize),
+        (min_y, max_y): (usize, usize),
+    ) -> Self {
+        let mask = ((1u32 << (max_x + 1)) - (1 << min_x)) as u16;
+        for (y, row) in self.rows.iter_mut().enumerate() {
+            *row = if (min_y..=max_y).contains(&y) {
+                *row & mask
+            } else {
+                0
+            };
+        }
+        self
+    }
+
+    // Combines the cells of two leaves row by row
+    pub fn combined(mut self, other: &LeafCell, op: impl Fn(u16, u16) -> u16) -> Self {
+        for (row, &other_row) in self.rows.iter_mut().zip(&other.rows) {
+            *row = op(*row, other_row);
+        }
+        self
+    }
+
+    pub fn flipped_horizontally(mut self) -> Self {
+        let width = self.width();
+        for row in &mut self.rows[..width] {
+            *row = row.reverse_bits() >> (16 - width);
+        }
+        self
+    }
+
+    pub fn flipped_vertically(mut self) -> Self {
+        let width = self.width();
+        self.rows[..width].reverse();
+        self
+    }
+
+    // Swaps rows and columns
+    pub fn transposed(&self) -> Self {
+        let mut leaf = LeafCell::empty(self.layer as usize);
+        for (y, row) in self.rows.iter().enumerate() {
+            for (x, column) in leaf.rows.iter_mut().enumerate() {
+                *column |= (row >> x & 1) << y;
+            }
+        }
+        leaf
+    }
+
+    // Inclusive columns and rows of the smallest rectangle around the live cells
+    pub fn extent(&self) -> Option<((usize, usize), (usize, usize))> {
+        let columns = self.rows.iter().fold(0, |columns, row| columns | row);
+        let min_y = self.rows.iter().position(|&row| row != 0)?;
+        let max_y = self.rows.iter().rposition(|&row| row != 0)?;
+        let min_x = columns.trailing_zeros() as usize;
+        let max_x = 15 - columns.leading_zeros() as usize;
+        Some(((min_x, max_x), (min_y, max_y)))
+    }
+
+    // Rows of the square twice as wide formed by four leaves
+    pub fn square_rows(quadrants: [&LeafCell; 4]) -> Vec<u32> {
+        let [nw, ne, sw, se] = quadrants;
+        let width = nw.width();
+        [(nw, ne), (sw, se)]
+            .into_iter()
+            .flat_map(|(w, e)| {
+                (0..width).map(move |y| w.rows[y] as u32 | (e.rows[y] as u32) << width)
+            })
+            .collect()
+    }
+
+    // The leaf of the given layer at (x, y) within a block of rows
+    pub fn from_rows(rows: &[u32], (x, y): (usize, usize), layer: usize) -> Self {
+        let mut leaf = LeafCell::empty(layer);
+        let mask = (1u32 << leaf.width()) - 1;
+        for (i, row) in leaf.rows[..1 << layer].iter_mut().enumerate() {
+            *row = (rows[y + i] >> x & mask) as u16;
+        }
+        leaf
+    }
+
+    // The leaf centred on the corner shared by four leaves
+    pub fn centered(quadrants: [&LeafCell; 4]) -> Self {
+        let layer = quadrants[0].layer as usize;
+        let half_width = 1 << (layer - 1);
+        LeafCell::from_rows(
+            &LeafCell::square_rows(quadrants),
+            (half_width, half_width),
+            layer,
+        )
+    }
+}
+
+// Rule counting the live cells of a neighbourhood, in a form which steps whole rows of
+// cells with bitwise operations
+#[derive(Clone, Copy, Debug)]
+pub struct CountRule {
+    // All ones for each neighbour in the neighbourhood, in ring order
+    neighbours: [u32; 8],
+    birth: u16,
+    survival: u16,
+}
+
+impl CountRule {
+    pub fn new(rule: &Rule) -> Option<Self> {
+        let Rule::LifeLike(rule) = rule else {
+            return None;
+        };
+        let (birth, survival) = rule.count_conditions()?;
+        let ring = rule.neighbourhood().ring();
+        Some(CountRule {
+            neighbours: std::array::from_fn(|i| if ring >> i & 1 == 1 { !0 } else { 0 }),
+            birth,
+            survival,
+        })
+    }
+
+    // Advances a block of rows by one generation in place. Cells beyond the block are
+    // read as dead, so the result is only correct away from its edges.
+    pub fn step_rows(&self, rows: &mut [u32]) {
+        let mut up = 0;
+        for y in 0..rows.len() {
+            let row = rows[y];
+            let down = rows.get(y + 1).copied().unwrap_or(0);
+            rows[y] = self.next_row(up, row, down);
+            up = row;
+        }
+    }
+
+    fn next_row(&self, up: u32, row: u32, down: u32) -> u32 {
+        // In ring order, clockwise from north
+        let shifted = [
+            up,
+            up >> 1,
+            row >> 1,
+            down >> 1,
+            down,
+            down << 1,
+            row << 1,
+            up << 1,
+        ];
+        let [n, ne, e, se, s, sw, w, nw] = std::array::from_fn(|i| shifted[i] & self.neighbours[i]);
+
+        // Binary digits of the live neighbour count of every cell in the row
+        let (ones_a, twos_a) = full_add(n, ne, e);
+        let (ones_b, twos_b) = full_add(se, s, sw);
+        let (ones_c, twos_c) = (w ^ nw, w & nw);
+        let (ones, twos_d) = full_add(ones_a, ones_b, ones_c);
+        let (twos_e, fours_a) = full_add(twos_a, twos_b, twos_c);
+        let (twos, fours_b) = (twos_e ^ twos_d, twos_e & twos_d);
+        let digits = [ones, twos, fours_a ^ fours_b, fours_a & fours_b];
+
+        // Only the counts which change anything need to be matched
+        (0..=Neighbourhood::MOORE.size())
+            .filter(|count| (self.birth | self.survival) >> count & 1 == 1)
+            .fold(0, |next, count| {
+                let matches = digits
+                    .iter()
+                    .enumerate()
+                    .fold(!0, |matches, (bit, &digit)| {
+                        matches & if count >> bit & 1 == 1 { digit } else { !digit }
+                    });
+                let born = if self.birth >> count & 1 == 1 {
+                    !row
+                } else {
+                    0
+                };
+                let survives = if self.survival >> count & 1 == 1 {
+                    row
+                } else {
+                    0
+                };
+                next | matches & (born | survives)
+            })
+    }
+}
+
+// Any two state rule on the 3x3 neighbourhood, tabulated as the next generation of
+// the center 2x2 of every 4x4 block. Bit y * 4 + x of a block holds the cell at (x, y),
+// and bit y * 2 + x of its center the cell at (x + 1, y + 1).
+#[derive(Clone, Debug)]
+pub struct TableRule {
+    // Shared since the table is rebuilt only when the rule changes
+    centers: Arc<[u8]>,
+}
+
+impl TableRule {
+    pub fn new(rule: &Rule) -> Option<Self> {
+        if rule.states() != 2 || rule.radius() != 1 {
+            return None;
+        }
+
+        let centers = (0..1 << 16)
+            .map(|block: usize| {
+                let state = |x: usize, y: usize| (block >> (y * 4 + x) & 1) as u8;
+                [(1, 1), (2, 1), (1, 2), (2, 2)].iter().enumerate().fold(
+                    0,
+                    |center, (i, &(x, y))| {
+                        let neighbourhood: [u8; 9] =
+                            std::array::from_fn(|j| state(x + j % 3 - 1, y + j / 3 - 1));
+                        center | rule.next_state(&neighbourhood) << i
+                    },
+                )
+            })
+            .collect();
+        Some(TableRule { centers })
+    }
+
+    pub fn next_center(&self, block: u16) -> u8 {
+        self.centers[block as usize]
+    }
+
+    // Advances the first `width` columns of a block of rows by one generation, two
+    // rows and two columns at a time. As with `CountRule::step_rows` only cells away
+    // from the edges are correct.
+    pub fn step_rows(&self, rows: &mut [u32], width: usize) {
+        let row = |y: usize| rows.get(y).map_or(0, |&row| (row as u64) << 1);
+        let mut next = [0; 2 << MAX_LEAF_LAYER];
+
+        for y in (0..rows.len()).step_by(2) {
+            let block_rows = [y.wrapping_sub(1), y, y + 1, y + 2].map(row);
+            for x in (0..width).step_by(2) {
+                let block = block_rows
+                    .iter()
+                    .enumerate()
+                    .fold(0, |block, (i, &row)| block | (row >> x & 0xf) << (i * 4));
+                let center = self.next_center(block as u16) as u32;
+                next[y] |= (center & 0b11) << x;
+                next[y + 1] |= (center >> 2) << x;
+            }
+        }
+        rows.copy_from_slice(&next[..rows.len()]);
+    }
+}
+
+// Sum and carry of three bits in each position
+fn full_add(a: u32, b: u32, c: u32) -> (u32, u32) {
+    let partial = a ^ b;
+    (partial ^ c, a & b | partial & c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::life::{
+        pack_unpack::{test::assert_same_cells, CellBounds},
+        tests::{assert_steps_match_naive, soup},
+        Life,
+    };
+
+    #[test]
+    fn leaves_split_and_join_along_rows() {
+        let empty = LeafCell::empty(3);
+        let leaf = empty.with_state(0, 0, 1).with_state(7, 7, 1);
+        assert_eq!(leaf.alive_count(), 2);
+
+        // The centred leaf takes the south east quarter of the north west leaf
+        let centered = LeafCell::centered([&leaf, &empty, &empty, &empty]);
+        assert_eq!(centered.alive_count(), 1);
+        assert_eq!(centered.state(3, 3), 1);
+
+        assert!(leaf.any_alive(4, 4, 4) && !leaf.any_alive(4, 0, 4));
+        assert_eq!(leaf.cleared_outside((0, 3), (0, 7)).alive_count(), 1);
+
+        let other = empty.with_state(7, 7, 1).with_state(2, 5, 1);
+        assert_eq!(leaf.combined(&other, |a, b| a ^ b).alive_count(), 2);
+        assert_eq!(other.extent(), Some(((2, 7), (5, 7))));
+        assert_eq!(empty.extent(), None);
+        assert_eq!(LeafCell::full(3).alive_count(), 64);
+
+        assert_eq!(other.transposed().state(5, 2), 1);
+        assert_eq!(other.flipped_horizontally().state(5, 5), 1);
+        assert_eq!(other.flipped_vertically().state(2, 2), 1);
+    }
+
+    #[test]
+    fn blinkers_oscillate_a_row_at_a_time() {
+        let rule = CountRule::new(&Rule::CONWAY).unwrap();
+        let mut rows = [0, 0b1110 << 8, 0, 0];
+        rule.step_rows(&mut rows);
+        assert_eq!(rows, [0b100 << 8, 0b100 << 8, 0b100 << 8, 0]);
+        rule.step_rows(&mut rows);
+        assert_eq!(rows, [0, 0b1110 << 8, 0, 0]);
+
+        assert!(CountRule::new(&"B2-a/S12".parse().unwrap()).is_none());
+    }
+
+    #[test]
+    fn tables_step_like_the_rule() {
+        let conway = CountRule::new(&Rule::CONWAY).unwrap();
+        let table = TableRule::new(&Rule::CONWAY).unwrap();
+        assert_eq!(table.next_center(0b0000_0111_0000_0000), 0b0101);
+        assert_eq!(table.next_center(0b0000_0110_0110_0000), 0b1111);
+
+        // The R-pentomino, away from the edges
+        let mut counted = [0, 0, 0b0110 << 2, 0b0011 << 2, 0b0010 << 2, 0, 0, 0];
+        let mut tabulated = counted;
+        for _ in 0..2 {
+            conway.step_rows(&mut counted);
+            table.step_rows(&mut tabulated, 8);
+        }
+        assert_eq!(counted[2..6], tabulated[2..6]);
+
+        assert!(TableRule::new(&"/2/3".parse().unwrap()).is_none());
+        assert!(CountRule::new(&"B2/S34H".parse().unwrap()).is_some());
+    }
+
+    #[test]
+    fn leaf_sizes_give_the_same_results() {
+        for rule in [
+            "B3/S23",
+            "B2-a/S12",
+            "B0123478/S01234678",
+            "R2,C0,S2-5,B3-4,NN",
+        ] {
+            let mut unpacked = Life::from_cell_positions(soup(24));
+            unpacked.set_leaf_size(1);
+            unpacked.set_rule(rule.parse().unwrap()).unwrap();
+            unpacked.step_by(100).unwrap();
+            unpacked.collect_garbage(&mut []);
+
+            for size in [2, 8, 16] {
+                let mut life = Life::from_cell_positions(soup(24));
+                life.set_leaf_size(size);
+                life.set_rule(rule.parse().unwrap()).unwrap();
+                for generations in [1, 3, 32, 64] {
+                    life.step_by(generations).unwrap();
+                }
+                assert_same_cells(&life, &unpacked);
+
+                // 2x2 leaves only replace the 2x2 nodes
+                life.collect_garbage(&mut []);
+                assert!(size < 8 || life.node_count() < unpacked.node_count());
+            }
+        }
+    }
+
+    #[test]
+    fn leaves_are_repacked_when_the_rule_changes() {
+        let cells = Life::from_cell_positions(soup(24));
+        let mut life = cells.clone();
+        assert_eq!(life.leaf_layer, 3);
+
+        life.set_rule("345/2/4".parse().unwrap()).unwrap();
+        assert_eq!(life.leaf_layer, 0);
+        assert_same_cells(&life, &cells);
+        assert_steps_match_naive(&mut life, &[1]);
+
+        // Decaying cells can't be stored as leaves, so they are revived
+        let decaying = life.cell_positions(0, CellBounds::ALL);
+        life.set_rule(Rule::CONWAY).unwrap();
+        assert_eq!(life.leaf_layer, 3);
+        assert_same_cells(&life, &Life::from_cell_positions(decaying));
+    }
+}
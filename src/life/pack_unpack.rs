@@ -1,12 +1,13 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use super::{
-    cell::{BaseCell, Cell, CompositeCell},
+    cell::{Cell, CompositeCell},
     cell_id::CellId,
+    leaf::LeafCell,
     Life,
 };
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct CellBounds {
     pub min_x: i64,
     pub min_y: i64,
@@ -14,25 +15,62 @@ pub struct CellBounds {
     pub max_y: i64,
 }
 
+impl CellBounds {
+    /// Bounds covering every cell.
+    pub const ALL: CellBounds = CellBounds {
+        min_x: i64::MIN,
+        min_y: i64::MIN,
+        max_x: i64::MAX,
+        max_y: i64::MAX,
+    };
+}
+
 impl Life {
-    pub fn from_cell_positions(max_depth: u8, points: Vec<(i64, i64)>) -> Self {
-        let mut life = Self::new(max_depth);
-        let base_alive_id = life.add_cell(Cell::Base(BaseCell::Alive));
-
-        let min_x = *points.iter().map(|(x, _)| x).min().unwrap_or(&0);
-        let min_y = *points.iter().map(|(_, y)| y).min().unwrap_or(&0);
-
-        let mut nodes = HashMap::<(u64, u64), CellId>::from_iter(
-            points
-                .iter()
-                .map(|(x, y)| ((x - min_x) as u64, (y - min_y) as u64))
-                .map(|point| (point, base_alive_id)),
-        );
+    pub fn from_cell_positions(points: Vec<(i64, i64)>) -> Self {
+        Self::from_cell_states(points.into_iter().map(|point| (point, 1)).collect())
+    }
+
+    pub fn from_cell_states(cells: Vec<((i64, i64), u8)>) -> Self {
+        let mut life = Self::new();
+
+        // Leaves only hold two states, the rule will repack the cells if it allows it
+        if cells.iter().any(|&(_, state)| state > 1) {
+            life.leaf_layer = 0;
+        }
+
+        // Leaves are kept on a grid of their own width, which padding, stepping and
+        // trimming all preserve, so that scaled positions are rounded consistently
+        let align = !((1 << life.leaf_layer) - 1);
+        let min_x = cells.iter().map(|((x, _), _)| *x).min().unwrap_or(0) & align;
+        let min_y = cells.iter().map(|((_, y), _)| *y).min().unwrap_or(0) & align;
+
+        let mut nodes = HashMap::<(u64, u64), CellId>::new();
+        let level = life.leaf_layer;
+        if level == 0 {
+            for ((x, y), state) in cells {
+                let point = ((x - min_x) as u64, (y - min_y) as u64);
+                nodes.insert(point, life.base_cell(state));
+            }
+        } else {
+            let mut leaves = HashMap::<(u64, u64), LeafCell>::new();
+            let mask = (1 << level) - 1;
+            for ((x, y), state) in cells {
+                let (x, y) = ((x - min_x) as u64, (y - min_y) as u64);
+                let leaf = leaves
+                    .entry((x >> level, y >> level))
+                    .or_insert(LeafCell::empty(level));
+                *leaf = leaf.with_state((x & mask) as usize, (y & mask) as usize, state);
+            }
+            for (point, leaf) in leaves {
+                nodes.insert(point, life.add_cell(Cell::Leaf(leaf)));
+            }
+        }
 
-        let mut level = 0;
+        let mut level = level as u8;
 
         while nodes.len() > 1 {
             let mut next_level: HashMap<(u64, u64), CellId> = HashMap::new();
+            life.reserve_layers(level as usize + 1);
 
             while !nodes.is_empty() {
                 let (x, y) = nodes.keys().next().unwrap();
@@ -61,86 +99,224 @@ impl Life {
 
         let root = nodes.values().next().unwrap();
         life.root = *root;
+        // The corner of the root is the corner of the pattern
+        let half_width = 1 << (life.root.layer().max(1) - 1);
+        life.root_centre = (min_x + half_width, min_y + half_width);
         life
     }
 
+    /// Positions of the blocks of 2^min_depth by 2^min_depth cells holding a non dead
+    /// cell within the bounds, numbered by dividing cell coordinates by the width of a
+    /// block, rounding down.
     pub fn cell_positions(&self, min_depth: u8, bounds: CellBounds) -> Vec<(i64, i64)> {
-        let half_width = 1 << (self.root.layer() - 1 - min_depth as usize);
         let mut positions = Vec::new();
+        if min_depth == 0 {
+            let root_corner = self.root_corner();
+            let visit = &mut |_, position| positions.push(position);
+            self.unpack_cells(visit, self.root, root_corner, bounds);
+            return positions;
+        }
 
-        let bound_div = 1 << min_depth as i64;
-
-        let mapped_bounds = CellBounds {
-            min_x: bounds.min_x / bound_div,
-            min_y: bounds.min_y / bound_div,
-            max_x: bounds.max_x / bound_div,
-            max_y: bounds.max_y / bound_div,
+        let block_width = 1 << min_depth;
+        let block_bounds = CellBounds {
+            min_x: bounds.min_x.div_euclid(block_width),
+            min_y: bounds.min_y.div_euclid(block_width),
+            max_x: bounds.max_x.div_euclid(block_width),
+            max_y: bounds.max_y.div_euclid(block_width),
         };
+        let mut visited = HashSet::new();
+        let visit = &mut |position| {
+            if visited.insert(position) {
+                positions.push(position);
+            }
+        };
+        self.visit_blocks(
+            block_width,
+            visit,
+            self.root,
+            self.root_corner(),
+            block_bounds,
+        );
+        positions
+    }
+
+    // Positions of every non dead cell within the bounds, along with its state
+    pub fn cell_states(&self, bounds: CellBounds) -> Vec<((i64, i64), u8)> {
+        let mut states = Vec::new();
 
         self.unpack_cells(
-            min_depth,
-            &mut positions,
+            &mut |state, position| states.push((position, state)),
             self.root,
-            (-half_width, -half_width),
-            mapped_bounds,
+            self.root_corner(),
+            bounds,
         );
-        positions
+        states
+    }
+
+    // Smallest rectangle around the non dead cells, found without listing them
+    pub fn pattern_bounds(&self) -> Option<CellBounds> {
+        let (x, y) = self.root_corner();
+        let (min_x, min_y, max_x, max_y) = self.extent(self.root, &mut HashMap::new())?;
+        Some(CellBounds {
+            min_x: x + min_x,
+            min_y: y + min_y,
+            max_x: x + max_x,
+            max_y: y + max_y,
+        })
+    }
+
+    // Bounds of the non dead cells relative to the corner of the cell
+    fn extent(
+        &self,
+        cell_id: CellId,
+        extents: &mut HashMap<CellId, Option<(i64, i64, i64, i64)>>,
+    ) -> Option<(i64, i64, i64, i64)> {
+        if cell_id.alive() == 0 {
+            return None;
+        }
+        if let Some(&extent) = extents.get(&cell_id) {
+            return extent;
+        }
+
+        let extent = match self.get_cell(cell_id).unwrap() {
+            Cell::Base(_) => Some((0, 0, 0, 0)),
+            Cell::Leaf(leaf) => leaf.extent().map(|((min_x, max_x), (min_y, max_y))| {
+                (min_x as i64, min_y as i64, max_x as i64, max_y as i64)
+            }),
+            Cell::Composite(cell) => {
+                let half_width = 1 << (cell_id.layer() - 1);
+                let children = [cell.nw, cell.ne, cell.sw, cell.se];
+                children
+                    .into_iter()
+                    .enumerate()
+                    .filter_map(|(i, child)| {
+                        let (x, y) = ((i % 2) as i64 * half_width, (i / 2) as i64 * half_width);
+                        let (min_x, min_y, max_x, max_y) = self.extent(child, extents)?;
+                        Some((x + min_x, y + min_y, x + max_x, y + max_y))
+                    })
+                    .reduce(|a, b| (a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3)))
+            }
+        };
+        extents.insert(cell_id, extent);
+        extent
+    }
+
+    // Position of the north west cell of the root
+    pub(super) fn root_corner(&self) -> (i64, i64) {
+        let half_width = 1 << (self.root.layer().max(1) - 1);
+        (
+            self.root_centre.0 - half_width,
+            self.root_centre.1 - half_width,
+        )
     }
 
+    // Visits every non dead cell within the bounds, with its state
     fn unpack_cells(
         &self,
-        max_depth: u8,
-        cells: &mut Vec<(i64, i64)>,
+        visit: &mut impl FnMut(u8, (i64, i64)),
         cell: CellId,
         (x, y): (i64, i64),
         bounds: CellBounds,
     ) {
-        let width = 1 << (cell.layer() - max_depth as usize);
+        let width = 1 << cell.layer();
         let half_width = width >> 1;
 
         if x > bounds.max_x
             || y > bounds.max_y
-            || x + width < bounds.min_x
-            || y + width < bounds.min_y
+            || x + width <= bounds.min_x
+            || y + width <= bounds.min_y
         {
             return;
         }
 
-        if cell.layer() <= max_depth as usize {
-            if cell.alive() > 0 {
-                cells.push((x, y));
-            }
-            return;
-        }
-
-        let cell = self.get_cell(cell).unwrap();
-        match cell {
-            Cell::Base(BaseCell::Alive) => {
-                cells.push((x, y));
+        match self.get_cell(cell).unwrap() {
+            Cell::Base(base) => visit(base.state(), (x, y)),
+            Cell::Leaf(leaf) => {
+                for leaf_y in 0..width {
+                    for leaf_x in 0..width {
+                        let (cell_x, cell_y) = (x + leaf_x, y + leaf_y);
+                        let in_bounds = (bounds.min_x..=bounds.max_x).contains(&cell_x)
+                            && (bounds.min_y..=bounds.max_y).contains(&cell_y);
+                        if in_bounds && leaf.any_alive(leaf_x as usize, leaf_y as usize, 1) {
+                            visit(1, (cell_x, cell_y));
+                        }
+                    }
+                }
             }
-            Cell::Base(BaseCell::Dead) => {}
             Cell::Composite(CompositeCell { nw, ne, sw, se, .. }) => {
                 if nw.alive() > 0 {
-                    self.unpack_cells(max_depth, cells, *nw, (x, y), bounds);
+                    self.unpack_cells(visit, nw, (x, y), bounds);
                 }
                 if ne.alive() > 0 {
-                    self.unpack_cells(max_depth, cells, *ne, (x + half_width, y), bounds);
+                    self.unpack_cells(visit, ne, (x + half_width, y), bounds);
                 }
                 if sw.alive() > 0 {
-                    self.unpack_cells(max_depth, cells, *sw, (x, y + half_width), bounds);
+                    self.unpack_cells(visit, sw, (x, y + half_width), bounds);
                 }
                 if se.alive() > 0 {
-                    self.unpack_cells(
-                        max_depth,
-                        cells,
-                        *se,
-                        (x + half_width, y + half_width),
-                        bounds,
-                    );
+                    self.unpack_cells(visit, se, (x + half_width, y + half_width), bounds);
                 }
             }
         }
     }
+
+    // Visits the blocks holding a non dead cell of the cell with its corner at (x, y).
+    // The root needn't line up with the blocks, so cells straddling several of them are
+    // split until each lies within one, down to single cells within leaves.
+    fn visit_blocks(
+        &self,
+        block_width: i64,
+        visit: &mut impl FnMut((i64, i64)),
+        cell: CellId,
+        (x, y): (i64, i64),
+        block_bounds: CellBounds,
+    ) {
+        if cell.alive() == 0 {
+            return;
+        }
+        let width = 1 << cell.layer();
+        let (min_x, min_y) = (x.div_euclid(block_width), y.div_euclid(block_width));
+        let max_x = (x + width - 1).div_euclid(block_width);
+        let max_y = (y + width - 1).div_euclid(block_width);
+        if min_x > block_bounds.max_x
+            || min_y > block_bounds.max_y
+            || max_x < block_bounds.min_x
+            || max_y < block_bounds.min_y
+        {
+            return;
+        }
+        if (min_x, min_y) == (max_x, max_y) {
+            visit((min_x, min_y));
+            return;
+        }
+
+        match self.get_cell(cell).unwrap() {
+            Cell::Leaf(leaf) => {
+                for leaf_y in 0..width {
+                    for leaf_x in 0..width {
+                        let block_x = (x + leaf_x).div_euclid(block_width);
+                        let block_y = (y + leaf_y).div_euclid(block_width);
+                        let in_bounds = (block_bounds.min_x..=block_bounds.max_x)
+                            .contains(&block_x)
+                            && (block_bounds.min_y..=block_bounds.max_y).contains(&block_y);
+                        if in_bounds && leaf.any_alive(leaf_x as usize, leaf_y as usize, 1) {
+                            visit((block_x, block_y));
+                        }
+                    }
+                }
+            }
+            Cell::Composite(cell) => {
+                let half_width = width / 2;
+                let [nw, ne, sw, se] = [cell.nw, cell.ne, cell.sw, cell.se];
+                self.visit_blocks(block_width, visit, nw, (x, y), block_bounds);
+                self.visit_blocks(block_width, visit, ne, (x + half_width, y), block_bounds);
+                self.visit_blocks(block_width, visit, sw, (x, y + half_width), block_bounds);
+                let se_corner = (x + half_width, y + half_width);
+                self.visit_blocks(block_width, visit, se, se_corner, block_bounds);
+            }
+            Cell::Base(_) => unreachable!("Single cells always lie within one block"),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -148,22 +324,96 @@ pub mod test {
     use super::*;
     use std::collections::HashSet;
 
+    #[track_caller]
+    pub fn assert_same_cells(actual: &Life, expected: &Life) {
+        let cells =
+            |life: &Life| -> HashSet<_> { life.cell_states(CellBounds::ALL).into_iter().collect() };
+        assert_eq!(cells(actual), cells(expected));
+    }
+
     #[test]
     fn test_pack_unpack() {
         let points = vec![(0, 0), (1, 0), (0, 1), (1, 1), (2, 2), (31, 7)];
-        let life = Life::from_cell_positions(8, points.clone());
-        let unpacked = life.cell_positions(
-            8,
-            CellBounds {
-                min_x: i64::MIN,
-                min_y: i64::MIN,
-                max_x: i64::MAX,
-                max_y: i64::MAX,
-            },
-        );
+        let life = Life::from_cell_positions(points.clone());
+        let unpacked = life.cell_positions(0, CellBounds::ALL);
 
         let expected: HashSet<(i64, i64)> = HashSet::from_iter(points);
         let actual = HashSet::from_iter(unpacked);
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    fn coordinates_survive_import_padding_and_trimming() {
+        let rle = "#CXRLE Pos=-40,25\nx = 3, y = 3, rule = B3/S23\nbo$2bo$3o!\n";
+        let mut life = Life::from_rle(rle);
+        let glider = HashSet::from([(-39, 25), (-38, 26), (-40, 27), (-39, 27), (-38, 27)]);
+        assert_eq!(
+            HashSet::from_iter(life.cell_positions(0, CellBounds::ALL)),
+            glider
+        );
+
+        life.step_by(400).unwrap();
+        let moved: HashSet<_> = glider.iter().map(|&(x, y)| (x + 100, y + 100)).collect();
+        assert_eq!(
+            HashSet::from_iter(life.cell_positions(0, CellBounds::ALL)),
+            moved
+        );
+        assert!(life.to_rle().starts_with("#CXRLE Pos=60,125 Gen=400"));
+    }
+
+    #[test]
+    fn scaled_positions_follow_absolute_coordinates() {
+        // The root doesn't line up with blocks of 32 cells
+        let life = Life::from_cell_positions(vec![(8, 0), (40, 0), (66, 0)]);
+        assert_ne!(life.root_corner().0.rem_euclid(32), 0);
+        let positions = life.cell_positions(5, CellBounds::ALL);
+        assert_eq!(
+            HashSet::from_iter(positions),
+            HashSet::from([(0, 0), (1, 0), (2, 0)])
+        );
+
+        let cells = [
+            (8, 0),
+            (40, 0),
+            (66, 0),
+            (-1, -33),
+            (-32, 31),
+            (5, 5),
+            (6, 5),
+        ];
+        let mut life = Life::from_cell_positions(cells.to_vec());
+        let (mut dx, mut dy) = (0, 0);
+        for (x, y) in [(0, 0), (3, -5), (-21, 13), (100, 7)] {
+            life.translate(x, y);
+            (dx, dy) = (dx + x, dy + y);
+            for depth in [1, 3, 5, 7] {
+                let expected: HashSet<_> = cells
+                    .iter()
+                    .map(|&(x, y)| ((x + dx) >> depth, (y + dy) >> depth))
+                    .collect();
+                let positions = life.cell_positions(depth, CellBounds::ALL);
+                assert_eq!(positions.len(), expected.len(), "depth {depth}");
+                assert_eq!(HashSet::from_iter(positions), expected, "depth {depth}");
+            }
+        }
+    }
+
+    #[test]
+    fn scaled_positions_round_down() {
+        let life = Life::from_cell_positions(vec![(-3, 2), (-1, -1), (4, -6)]);
+        let positions: HashSet<_> = life
+            .cell_positions(1, CellBounds::ALL)
+            .into_iter()
+            .collect();
+        assert_eq!(positions, HashSet::from([(-2, 1), (-1, -1), (2, -3)]));
+
+        // Cells just outside the bounds aren't listed
+        let bounds = CellBounds {
+            min_x: 0,
+            min_y: -6,
+            max_x: 3,
+            max_y: 0,
+        };
+        assert!(life.cell_positions(0, bounds).is_empty());
+    }
 }
@@ -0,0 +1,386 @@
+// Pasting one universe into another a node at a time. Nodes are imported and shifted
+// into place with memos keyed on the nodes involved, so material repeated across the
+// pasted pattern is only handled once.
+
+use std::collections::HashMap;
+
+use super::{
+    boolean::Operation, cell::Cell, cell_id::CellId, leaf::LeafCell, pack_unpack::CellBounds,
+    region::UnboundedEdit, Life,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PasteMode {
+    /// Replaces every cell within the bounds of the pasted pattern
+    Copy,
+    /// Keeps the cells which are alive in either pattern
+    Or,
+    /// Keeps the cells which are alive in exactly one of the patterns
+    Xor,
+    /// Kills the cells which are alive in the pasted pattern
+    AndNot,
+}
+
+impl Life {
+    /// Pastes another universe into this one, moving its cells by (x, y). Cells are
+    /// combined as displayed, so under B0 rules the modes which would change empty space
+    /// fail unless it is dead in the pasted universe.
+    pub fn paste(
+        &mut self,
+        other: &Life,
+        x: i64,
+        y: i64,
+        mode: PasteMode,
+    ) -> Result<(), UnboundedEdit> {
+        let backgrounds = (self.background_state(), other.background_state());
+        let operation = match mode {
+            PasteMode::Copy => {
+                let Some(bounds) = other.pattern_bounds() else {
+                    return Ok(());
+                };
+                let bounds = CellBounds {
+                    min_x: bounds.min_x + x,
+                    min_y: bounds.min_y + y,
+                    max_x: bounds.max_x + x,
+                    max_y: bounds.max_y + y,
+                };
+                self.clear_region(bounds)?;
+                self.copy(other, (x, y), bounds);
+                return Ok(());
+            }
+            PasteMode::Or => Operation::Or,
+            PasteMode::Xor => Operation::Xor,
+            PasteMode::AndNot => Operation::AndNot,
+        };
+        if operation.background(backgrounds) != backgrounds.0 {
+            return Err(UnboundedEdit);
+        }
+        self.combine(other, (x, y), operation, backgrounds);
+        Ok(())
+    }
+
+    // Writes the displayed cells of the other universe, moved by (x, y), over the
+    // region they cover, which must already be cleared
+    fn copy(&mut self, other: &Life, (x, y): (i64, i64), bounds: CellBounds) {
+        let mut pasted = self.pasted(other, (x, y));
+
+        // The other universe only stores its cells unlike empty space
+        if other.background_state() != 0 {
+            let empty = self.empty_of_layer(self.root.layer() as u8);
+            let filled = self.filled(empty, self.root_corner(), bounds);
+            pasted = self.combined(filled, pasted, Operation::AndNot, &mut HashMap::new());
+        }
+
+        // The cleared region holds nothing but empty space, whatever its state
+        let operation = match self.background_state() {
+            0 => Operation::Or,
+            _ => Operation::AndNot,
+        };
+        self.root = self.combined(self.root, pasted, operation, &mut HashMap::new());
+    }
+
+    // Combines the displayed cells of this universe with those of the other one, with its
+    // cells moved by (x, y), given the state of empty space in each. The result is stored
+    // against the background the operation leaves.
+    pub(super) fn combine(
+        &mut self,
+        other: &Life,
+        (x, y): (i64, i64),
+        operation: Operation,
+        backgrounds: (u8, u8),
+    ) {
+        let (operation, swapped) = operation.on_stored(backgrounds);
+        if other.root.alive() == 0 {
+            if operation == Operation::And || swapped {
+                self.root = self.empty_of_layer(self.root.layer() as u8);
+            }
+            return;
+        }
+
+        let pasted = self.pasted(other, (x, y));
+        self.root = match swapped {
+            false => self.combined(self.root, pasted, operation, &mut HashMap::new()),
+            true => self.combined(pasted, self.root, operation, &mut HashMap::new()),
+        };
+    }
+
+    // The stored cells of the other universe moved by (x, y), in a cell lined up with
+    // the root, which is padded to hold them
+    fn pasted(&mut self, other: &Life, (x, y): (i64, i64)) -> CellId {
+        let layer = other.root.layer().max(self.leaf_layer + 1);
+        let (corner_x, corner_y) = other.root_corner();
+        let corner = (corner_x + x, corner_y + y);
+        let width = 1 << layer;
+        self.pad_to_contain(corner, (corner.0 + width - 1, corner.1 + width - 1));
+
+        let pasted = self.imported(other, layer);
+        self.placed(pasted, corner)
+    }
+
+    // The root of the other universe, rebuilt in this one at no less than the given layer
+    fn imported(&mut self, other: &Life, layer: usize) -> CellId {
+        if other.root.layer() == layer {
+            return self.imported_cell(other, other.root, &mut HashMap::new());
+        }
+
+        // The root is no wider than the leaves of this universe
+        let width = 1 << layer;
+        let mut cells = vec![0; width * width];
+        other.read_states(other.root, &mut cells, width, (0, 0));
+        self.build_from_states(&cells, width, (0, 0), layer)
+    }
+
+    pub(super) fn imported_cell(
+        &mut self,
+        other: &Life,
+        cell_id: CellId,
+        imported: &mut HashMap<CellId, CellId>,
+    ) -> CellId {
+        if cell_id.alive() == 0 {
+            return self.empty_of_layer(cell_id.layer() as u8);
+        }
+        if let Some(&id) = imported.get(&cell_id) {
+            return id;
+        }
+
+        // The universes may pack different layers into leaves
+        let layer = cell_id.layer();
+        let res = match other.get_cell(cell_id).unwrap() {
+            Cell::Composite(cell) if layer > self.leaf_layer => {
+                let children = [cell.nw, cell.ne, cell.sw, cell.se];
+                let [nw, ne, sw, se] =
+                    children.map(|child| self.imported_cell(other, child, imported));
+                self.join(nw, ne, sw, se)
+            }
+            Cell::Leaf(leaf) if layer == self.leaf_layer => self.add_cell(Cell::Leaf(leaf)),
+            _ => {
+                let width = 1 << layer;
+                let mut cells = vec![0; width * width];
+                other.read_states(cell_id, &mut cells, width, (0, 0));
+                self.build_from_states(&cells, width, (0, 0), layer)
+            }
+        };
+        imported.insert(cell_id, res);
+        res
+    }
+
+    // A cell the size of the root, holding the given cell with its corner at the given
+    // position within the root
+    pub(super) fn placed(&mut self, cell_id: CellId, (x, y): (i64, i64)) -> CellId {
+        let root_layer = self.root.layer();
+        let mut cell_id = cell_id;
+        for layer in cell_id.layer()..root_layer {
+            let empty = self.empty_of_layer(layer as u8);
+            cell_id = self.join(cell_id, empty, empty, empty);
+        }
+
+        // Unless it lines up with the root, the padded cell is the south or east one of
+        // a block of four with the root inside
+        let width = 1 << root_layer;
+        let (root_x, root_y) = self.root_corner();
+        let (east, offset_x) = match x - root_x {
+            0 => (0, 0),
+            dx => (1, (width - dx) as usize),
+        };
+        let (south, offset_y) = match y - root_y {
+            0 => (0, 0),
+            dy => (1, (width - dy) as usize),
+        };
+
+        let mut quadrants = [self.empty_of_layer(root_layer as u8); 4];
+        quadrants[south * 2 + east] = cell_id;
+        self.shifted(quadrants, (offset_x, offset_y), &mut HashMap::new())
+    }
+
+    // The square at (x, y) within the block of four cells, which is no further than the
+    // width of a cell from its corner. The position within the cells of each layer is
+    // the same throughout a shift, so the memo is keyed on the cells alone.
+    fn shifted(
+        &mut self,
+        quadrants: [CellId; 4],
+        (x, y): (usize, usize),
+        shifted: &mut HashMap<[CellId; 4], CellId>,
+    ) -> CellId {
+        let layer = quadrants[0].layer();
+        if (x, y) == (0, 0) {
+            return quadrants[0];
+        }
+        if quadrants.iter().all(|quadrant| quadrant.alive() == 0) {
+            return self.empty_of_layer(layer as u8);
+        }
+        if let Some(&id) = shifted.get(&quadrants) {
+            return id;
+        }
+
+        let res = if layer == self.leaf_layer {
+            let leaves = quadrants.map(|quadrant| self.get_cell(quadrant).unwrap().into_leaf());
+            let leaf =
+                LeafCell::from_rows(&LeafCell::square_rows(leaves.each_ref()), (x, y), layer);
+            self.add_cell(Cell::Leaf(leaf))
+        } else {
+            let children = quadrants.map(|quadrant| {
+                let cell = self.get_cell(quadrant).unwrap().into_composite();
+                [cell.nw, cell.ne, cell.sw, cell.se]
+            });
+            // Children in a grid of four by four across the block
+            let child = |column: usize, row: usize| {
+                children[row / 2 * 2 + column / 2][row % 2 * 2 + column % 2]
+            };
+
+            let half_width = 1 << (layer - 1);
+            let [nw, ne, sw, se] = std::array::from_fn(|i| {
+                let column = (x + i % 2 * half_width) / half_width;
+                let row = (y + i / 2 * half_width) / half_width;
+                let quadrants = [
+                    child(column, row),
+                    child(column + 1, row),
+                    child(column, row + 1),
+                    child(column + 1, row + 1),
+                ];
+                self.shifted(quadrants, (x % half_width, y % half_width), shifted)
+            });
+            self.join(nw, ne, sw, se)
+        };
+        shifted.insert(quadrants, res);
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::life::pack_unpack::test::assert_same_cells;
+    use std::collections::HashSet;
+
+    fn scattered(seed: u64, count: usize) -> Vec<(i64, i64)> {
+        let mut state = seed;
+        (0..count)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                ((state % 40) as i64 - 20, (state / 40 % 40) as i64 - 20)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn modes_match_set_operations() {
+        let cells: HashSet<_> = scattered(1, 300).into_iter().collect();
+        for leaf_size in [1, 4, 8, 16] {
+            for (x, y) in [(0, 0), (16, -8), (-13, 37), (5, 3)] {
+                let mut pasted = Life::from_cell_positions(scattered(2, 200));
+                pasted.set_leaf_size(leaf_size);
+                let moved: HashSet<_> = scattered(2, 200)
+                    .into_iter()
+                    .map(|(cell_x, cell_y)| (cell_x + x, cell_y + y))
+                    .collect();
+                let bounds = pasted.pattern_bounds().unwrap();
+                let uncovered = cells.iter().filter(|&&(cell_x, cell_y)| {
+                    !(bounds.min_x + x..=bounds.max_x + x).contains(&cell_x)
+                        || !(bounds.min_y + y..=bounds.max_y + y).contains(&cell_y)
+                });
+
+                let expected = [
+                    (PasteMode::Copy, uncovered.chain(&moved).copied().collect()),
+                    (PasteMode::Or, cells.union(&moved).copied().collect()),
+                    (
+                        PasteMode::Xor,
+                        cells.symmetric_difference(&moved).copied().collect(),
+                    ),
+                    (
+                        PasteMode::AndNot,
+                        cells.difference(&moved).copied().collect(),
+                    ),
+                ];
+                for (mode, expected) in expected {
+                    let mut life = Life::from_cell_positions(cells.iter().copied().collect());
+                    life.paste(&pasted, x, y, mode).unwrap();
+                    let actual: HashSet<_> = life
+                        .cell_positions(0, CellBounds::ALL)
+                        .into_iter()
+                        .collect();
+                    assert_eq!(
+                        actual, expected,
+                        "{mode:?} at ({x}, {y}), leaf size {leaf_size}"
+                    );
+                }
+            }
+        }
+
+        // Under B0 rules, cells are combined as displayed in whichever phase each
+        // universe is, and modes which would change empty space fail
+        let b0 = |seed: u64, steps: u128| {
+            let mut life = Life::from_cell_positions(scattered(seed, 1200));
+            life.set_rule("B0123478/S01234678".parse().unwrap())
+                .unwrap();
+            life.step_by(steps).unwrap();
+            life
+        };
+        let (x, y) = (7, -3);
+        for (steps, other_steps) in [(1, 0), (0, 1), (1, 1)] {
+            let pasted = b0(2, other_steps);
+            let bounds = pasted.pattern_bounds().unwrap();
+            for mode in [
+                PasteMode::Copy,
+                PasteMode::Or,
+                PasteMode::Xor,
+                PasteMode::AndNot,
+            ] {
+                let before = b0(1, steps);
+                let mut life = before.clone();
+                let backgrounds = (before.background_state(), pasted.background_state());
+                let fails = match mode {
+                    PasteMode::Copy => false,
+                    PasteMode::Or => backgrounds == (0, 1),
+                    PasteMode::Xor => backgrounds.1 == 1,
+                    PasteMode::AndNot => backgrounds == (1, 1),
+                };
+                assert_eq!(life.paste(&pasted, x, y, mode).is_err(), fails, "{mode:?}");
+                if fails {
+                    continue;
+                }
+
+                for cell_x in -40..60 {
+                    for cell_y in -40..60 {
+                        let state = before.get_cell_at(cell_x, cell_y);
+                        let other = pasted.get_cell_at(cell_x - x, cell_y - y);
+                        let covered = (bounds.min_x..=bounds.max_x).contains(&(cell_x - x))
+                            && (bounds.min_y..=bounds.max_y).contains(&(cell_y - y));
+                        let expected = match mode {
+                            PasteMode::Copy if covered => other,
+                            PasteMode::Copy => state,
+                            PasteMode::Or => state | other,
+                            PasteMode::Xor => state ^ other,
+                            PasteMode::AndNot => state & !other & 1,
+                        };
+                        assert_eq!(
+                            life.get_cell_at(cell_x, cell_y),
+                            expected,
+                            "{mode:?} at ({cell_x}, {cell_y}) with backgrounds {backgrounds:?}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pasted_gliders_step_like_a_fresh_universe() {
+        let glider = [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+        let stamp = Life::from_cell_positions(glider.to_vec());
+        let mut pasted = Life::new();
+        let mut cells = Vec::new();
+        for i in 0..32 {
+            pasted
+                .paste(&stamp, i * 10 - 150, i * 3, PasteMode::Or)
+                .unwrap();
+            cells.extend(glider.map(|(x, y)| (x + i * 10 - 150, y + i * 3)));
+        }
+        let mut fresh = Life::from_cell_positions(cells);
+
+        pasted.step_by(100).unwrap();
+        fresh.step_by(100).unwrap();
+        assert_same_cells(&pasted, &fresh);
+    }
+}
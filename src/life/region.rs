@@ -0,0 +1,365 @@
+// Clearing and extracting rectangles of a universe. Only the cells straddling the edges
+// of the rectangle are rebuilt, the cells on either side are kept or dropped whole.
+
+use std::{collections::HashMap, fmt};
+
+use super::{
+    cell::{BaseCell, Cell},
+    cell_id::CellId,
+    leaf::LeafCell,
+    pack_unpack::CellBounds,
+    Life,
+};
+
+// Regions filled with live cells are kept well within the coordinates a root can reach
+const MAX_FILLED_COORDINATE: i64 = 1 << 60;
+
+/// Returned by edits which would leave infinitely many cells unlike empty space, such as
+/// killing every cell outside a region on a generation where empty space is alive.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct UnboundedEdit;
+
+impl fmt::Display for UnboundedEdit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The edit would change infinitely many cells of empty space"
+        )
+    }
+}
+
+impl std::error::Error for UnboundedEdit {}
+
+#[derive(PartialEq, Eq)]
+enum Overlap {
+    None,
+    Partial,
+    Full,
+}
+
+// How much of the square of the given width at (x, y) lies within the bounds
+fn overlap((x, y): (i64, i64), width: i64, bounds: CellBounds) -> Overlap {
+    if x > bounds.max_x
+        || y > bounds.max_y
+        || x + width - 1 < bounds.min_x
+        || y + width - 1 < bounds.min_y
+    {
+        Overlap::None
+    } else if x >= bounds.min_x
+        && y >= bounds.min_y
+        && x + width - 1 <= bounds.max_x
+        && y + width - 1 <= bounds.max_y
+    {
+        Overlap::Full
+    } else {
+        Overlap::Partial
+    }
+}
+
+impl Life {
+    /// Kills every cell within the bounds. On generations where a B0 rule has made empty
+    /// space alive, the bounds must be finite.
+    pub fn clear_region(&mut self, bounds: CellBounds) -> Result<(), UnboundedEdit> {
+        self.fill_region(bounds, 0)
+    }
+
+    // Sets every cell within the bounds to the given displayed state
+    pub(super) fn fill_region(
+        &mut self,
+        bounds: CellBounds,
+        state: u8,
+    ) -> Result<(), UnboundedEdit> {
+        if state == self.background_state() {
+            self.root = self.cleared(self.root, self.root_corner(), bounds, true);
+            return Ok(());
+        }
+
+        // Cells unlike empty space are stored as live ones
+        let limit = -MAX_FILLED_COORDINATE..MAX_FILLED_COORDINATE;
+        if ![bounds.min_x, bounds.min_y, bounds.max_x, bounds.max_y]
+            .iter()
+            .all(|bound| limit.contains(bound))
+        {
+            return Err(UnboundedEdit);
+        }
+        if bounds.min_x <= bounds.max_x && bounds.min_y <= bounds.max_y {
+            let (min, max) = ((bounds.min_x, bounds.min_y), (bounds.max_x, bounds.max_y));
+            self.pad_to_contain(min, max);
+            self.root = self.filled(self.root, self.root_corner(), bounds);
+        }
+        Ok(())
+    }
+
+    /// Kills every cell outside the bounds, which is only possible while empty space is
+    /// dead.
+    pub fn clear_outside(&mut self, bounds: CellBounds) -> Result<(), UnboundedEdit> {
+        if self.background_state() != 0 {
+            return Err(UnboundedEdit);
+        }
+        self.root = self.cleared(self.root, self.root_corner(), bounds, false);
+        Ok(())
+    }
+
+    /// Copies the cells within the bounds into a new universe with the same rule,
+    /// topology and generation, keeping their positions.
+    pub fn extract(&self, bounds: CellBounds) -> Life {
+        let mut life = Life::new();
+        life.rule = self.rule.clone();
+        life.topology = self.topology;
+        life.packed_leaf_layer = self.packed_leaf_layer;
+        life.update_phase_rules();
+        life.generation = self.generation;
+        life.set_gc_threshold(self.gc_threshold);
+        life.set_memory_budget(self.memory_budget);
+        life.threads = self.threads;
+
+        life.reserve_layers(self.root.layer());
+        let corner = self.root_corner();
+        life.root = life.extracted(self, self.root, corner, bounds, &mut HashMap::new());
+        life.root_centre = self.root_centre;
+        life.trim();
+        life
+    }
+
+    // Clears the cells within the bounds, or those outside them
+    pub(super) fn cleared(
+        &mut self,
+        cell_id: CellId,
+        (x, y): (i64, i64),
+        bounds: CellBounds,
+        inside: bool,
+    ) -> CellId {
+        let width = 1 << cell_id.layer();
+        if cell_id.alive() == 0 {
+            return cell_id;
+        }
+        match overlap((x, y), width, bounds) {
+            Overlap::None if inside => return cell_id,
+            Overlap::Full if !inside => return cell_id,
+            Overlap::None | Overlap::Full => return self.empty_of_layer(cell_id.layer() as u8),
+            Overlap::Partial => (),
+        }
+
+        let cell = match self.get_cell(cell_id).unwrap() {
+            Cell::Leaf(leaf) => {
+                let clamp = |min: i64, max: i64, origin: i64| {
+                    (
+                        (min - origin).max(0) as usize,
+                        (max - origin).min(width - 1) as usize,
+                    )
+                };
+                let within = leaf.cleared_outside(
+                    clamp(bounds.min_x, bounds.max_x, x),
+                    clamp(bounds.min_y, bounds.max_y, y),
+                );
+                let leaf = match inside {
+                    true => leaf.combined(&within, |row, within| row & !within),
+                    false => within,
+                };
+                return self.add_cell(Cell::Leaf(leaf));
+            }
+            cell => cell.into_composite(),
+        };
+
+        let half_width = width / 2;
+        let [nw, ne, sw, se] = [cell.nw, cell.ne, cell.sw, cell.se];
+
+        let nw = self.cleared(nw, (x, y), bounds, inside);
+        let ne = self.cleared(ne, (x + half_width, y), bounds, inside);
+        let sw = self.cleared(sw, (x, y + half_width), bounds, inside);
+        let se = self.cleared(se, (x + half_width, y + half_width), bounds, inside);
+        self.join(nw, ne, sw, se)
+    }
+
+    // Brings every cell within the bounds to life
+    pub(super) fn filled(
+        &mut self,
+        cell_id: CellId,
+        (x, y): (i64, i64),
+        bounds: CellBounds,
+    ) -> CellId {
+        let width = 1 << cell_id.layer();
+        match overlap((x, y), width, bounds) {
+            Overlap::None => return cell_id,
+            Overlap::Full => return self.full_of_layer(cell_id.layer()),
+            Overlap::Partial => (),
+        }
+
+        let cell = match self.get_cell(cell_id).unwrap() {
+            Cell::Leaf(leaf) => {
+                let clamp = |min: i64, max: i64, origin: i64| {
+                    (
+                        (min - origin).max(0) as usize,
+                        (max - origin).min(width - 1) as usize,
+                    )
+                };
+                let within = LeafCell::full(leaf.layer() as usize).cleared_outside(
+                    clamp(bounds.min_x, bounds.max_x, x),
+                    clamp(bounds.min_y, bounds.max_y, y),
+                );
+                let leaf = leaf.combined(&within, |row, within| row | within);
+                return self.add_cell(Cell::Leaf(leaf));
+            }
+            cell => cell.into_composite(),
+        };
+
+        let half_width = width / 2;
+        let [nw, ne, sw, se] = [cell.nw, cell.ne, cell.sw, cell.se];
+
+        let nw = self.filled(nw, (x, y), bounds);
+        let ne = self.filled(ne, (x + half_width, y), bounds);
+        let sw = self.filled(sw, (x, y + half_width), bounds);
+        let se = self.filled(se, (x + half_width, y + half_width), bounds);
+        self.join(nw, ne, sw, se)
+    }
+
+    fn full_of_layer(&self, layer: usize) -> CellId {
+        let (mut full_id, bottom) = if self.leaf_layer > 0 && layer >= self.leaf_layer {
+            let leaf = LeafCell::full(self.leaf_layer);
+            (self.add_cell(Cell::Leaf(leaf)), self.leaf_layer)
+        } else {
+            (self.add_cell(Cell::Base(BaseCell::ALIVE)), 0)
+        };
+        for _ in bottom..layer {
+            full_id = self.add_cell(Cell::composite(full_id, full_id, full_id, full_id));
+        }
+        full_id
+    }
+
+    // The cells of a cell of the other universe within the bounds, rebuilt in this one
+    fn extracted(
+        &mut self,
+        other: &Life,
+        cell_id: CellId,
+        (x, y): (i64, i64),
+        bounds: CellBounds,
+        imported: &mut HashMap<CellId, CellId>,
+    ) -> CellId {
+        let width = 1 << cell_id.layer();
+        match overlap((x, y), width, bounds) {
+            _ if cell_id.alive() == 0 => return self.empty_of_layer(cell_id.layer() as u8),
+            Overlap::None => return self.empty_of_layer(cell_id.layer() as u8),
+            Overlap::Full => return self.imported_cell(other, cell_id, imported),
+            Overlap::Partial => (),
+        }
+
+        let cell = match other.get_cell(cell_id).unwrap() {
+            Cell::Composite(cell) => cell,
+            _ => {
+                let imported = self.imported_cell(other, cell_id, imported);
+                return self.cleared(imported, (x, y), bounds, false);
+            }
+        };
+
+        let half_width = width / 2;
+        let nw = self.extracted(other, cell.nw, (x, y), bounds, imported);
+        let ne = self.extracted(other, cell.ne, (x + half_width, y), bounds, imported);
+        let sw = self.extracted(other, cell.sw, (x, y + half_width), bounds, imported);
+        let se = self.extracted(
+            other,
+            cell.se,
+            (x + half_width, y + half_width),
+            bounds,
+            imported,
+        );
+        self.join(nw, ne, sw, se)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::life::pack_unpack::test::assert_same_cells;
+    use std::collections::HashSet;
+
+    #[test]
+    fn regions_are_cleared_and_extracted() {
+        let cells: Vec<_> = (-30..30)
+            .flat_map(|x: i64| (-30..30).map(move |y: i64| (x, y)))
+            .filter(|(x, y)| (x * 7 + y * 3).rem_euclid(5) == 0)
+            .collect();
+        let bounds = CellBounds {
+            min_x: -13,
+            min_y: -2,
+            max_x: 9,
+            max_y: 21,
+        };
+        let within = |&(x, y): &(i64, i64)| {
+            (bounds.min_x..=bounds.max_x).contains(&x) && (bounds.min_y..=bounds.max_y).contains(&y)
+        };
+        let inside: HashSet<_> = cells.iter().copied().filter(within).collect();
+        let outside: HashSet<_> = cells.iter().copied().filter(|cell| !within(cell)).collect();
+
+        let life = Life::from_cell_positions(cells);
+        let extracted = life.extract(bounds);
+        assert_eq!(
+            HashSet::from_iter(extracted.cell_positions(0, CellBounds::ALL)),
+            inside
+        );
+        assert_eq!(extracted.pattern_bounds().unwrap().min_x, -13);
+
+        let mut cleared = life.clone();
+        cleared.clear_region(bounds).unwrap();
+        assert_eq!(
+            HashSet::from_iter(cleared.cell_positions(0, CellBounds::ALL)),
+            outside
+        );
+
+        let mut kept = life.clone();
+        kept.clear_outside(bounds).unwrap();
+        assert_eq!(
+            HashSet::from_iter(kept.cell_positions(0, CellBounds::ALL)),
+            inside
+        );
+    }
+
+    #[test]
+    fn extracted_patterns_keep_stepping() {
+        // A glider next to a blinker, which is cut away
+        let mut life = Life::from_cell_positions(vec![
+            (1, 0),
+            (2, 1),
+            (0, 2),
+            (1, 2),
+            (2, 2),
+            (20, 0),
+            (20, 1),
+            (20, 2),
+        ]);
+        life.step_by(4).unwrap();
+        let mut glider = life.extract(CellBounds {
+            min_x: -10,
+            min_y: -10,
+            max_x: 10,
+            max_y: 10,
+        });
+        assert_eq!(glider.generation(), 4);
+
+        glider.step_by(4).unwrap();
+        let expected = Life::from_cell_positions(vec![(3, 2), (4, 3), (2, 4), (3, 4), (4, 4)]);
+        assert_same_cells(&glider, &expected);
+    }
+
+    #[test]
+    fn b0_rules_clear_displayed_cells() {
+        let mut life = Life::from_cell_positions(vec![(0, 0)]);
+        life.set_rule("B0123478/S01234678".parse().unwrap())
+            .unwrap();
+        life.step().unwrap();
+        life.set_cell_at(5, 5, 0);
+        let bounds = CellBounds {
+            min_x: 0,
+            min_y: 0,
+            max_x: 10,
+            max_y: 10,
+        };
+        life.clear_region(bounds).unwrap();
+        assert_eq!(life.get_cell_at(5, 5), 0);
+        assert_eq!(life.get_cell_at(3, 7), 0);
+        assert_eq!(life.get_cell_at(11, 10), 1);
+        assert_eq!(life.get_cell_at(-1, 0), 1);
+
+        assert!(life.clear_region(CellBounds::ALL).is_err());
+        assert!(life.clear_outside(bounds).is_err());
+    }
+}
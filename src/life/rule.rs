@@ -0,0 +1,198 @@
+mod larger_than_life;
+mod life_like;
+mod neighbourhood;
+mod table;
+mod topology;
+
+use std::{fmt, str::FromStr, sync::Arc};
+
+pub use larger_than_life::LargerThanLife;
+pub use life_like::LifeLike;
+pub use neighbourhood::Neighbourhood;
+pub use table::RuleTable;
+pub use topology::{Topology, TwistedEdges};
+
+// Rules with B0 turn the empty plane alive, which a quadtree of empty cells can't
+// represent. As in Golly, they are emulated by storing some generations with every
+// cell inverted.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum B0Emulation {
+    None,
+    // Odd generations are stored inverted
+    Alternating,
+    // With S8 the plane stays alive, so every generation after the first is stored inverted
+    Inverted,
+}
+
+impl B0Emulation {
+    // Phase 1 stores every cell inverted
+    pub fn phase_of(self, generation: u128) -> u8 {
+        match self {
+            B0Emulation::None => 0,
+            B0Emulation::Alternating => (generation % 2) as u8,
+            B0Emulation::Inverted => (generation > 0) as u8,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum Rule {
+    LifeLike(LifeLike),
+    // Shared since tables can be large and a universe is cloned with its rule
+    Table(Arc<RuleTable>),
+    LargerThanLife(LargerThanLife),
+}
+
+impl Rule {
+    pub const CONWAY: Rule = Rule::LifeLike(LifeLike::CONWAY);
+    pub const MAX_STATES: u16 = 256;
+
+    // Loads a Golly .rule file, using its @TABLE or @TREE section
+    pub fn from_rule_file(rule_file: &str) -> Result<Self, RuleParseError> {
+        Ok(Rule::Table(Arc::new(RuleTable::parse(rule_file)?)))
+    }
+
+    // Splits off a bounded grid suffix, as in B3/S23:T30,20
+    pub fn parse_with_topology(s: &str) -> Result<(Self, Topology), RuleParseError> {
+        match s.split_once(':') {
+            Some((rule, topology)) => Ok((rule.parse()?, topology.parse()?)),
+            None => Ok((s.parse()?, Topology::Infinite)),
+        }
+    }
+
+    pub fn states(&self) -> u16 {
+        match self {
+            Rule::LifeLike(rule) => rule.states(),
+            Rule::Table(table) => table.states(),
+            Rule::LargerThanLife(rule) => rule.states(),
+        }
+    }
+
+    // Distance at which cells affect each other in a single generation
+    pub fn radius(&self) -> u32 {
+        match self {
+            Rule::LargerThanLife(rule) => rule.radius(),
+            _ => 1,
+        }
+    }
+
+    // The emulation needed by the rule, along with the rules stepping the stored cells
+    // from each of the two phases of the emulation
+    pub fn b0_emulation(&self) -> (B0Emulation, [Rule; 2]) {
+        let rule = match self {
+            Rule::LifeLike(rule) if rule.has_b0() => rule,
+            _ => return (B0Emulation::None, [self.clone(), self.clone()]),
+        };
+
+        // Even generations are stored as they are, so the first step inverts its result.
+        // Without S8 the next step inverts its input back again.
+        let first = Rule::LifeLike(rule.with_inverted_output());
+        if rule.has_s8() {
+            let rest = Rule::LifeLike(rule.with_inverted_inputs().with_inverted_output());
+            (B0Emulation::Inverted, [first, rest])
+        } else {
+            let second = Rule::LifeLike(rule.with_inverted_inputs());
+            (B0Emulation::Alternating, [first, second])
+        }
+    }
+
+    pub fn neighbourhood(&self) -> Neighbourhood {
+        match self {
+            Rule::LifeLike(rule) => rule.neighbourhood(),
+            Rule::Table(table) => table.neighbourhood(),
+            Rule::LargerThanLife(_) => Neighbourhood::MOORE,
+        }
+    }
+
+    // Takes the states of a 3x3 neighbourhood, read row by row from the north west
+    pub fn next_state(&self, neighbourhood: &[u8; 9]) -> u8 {
+        match self {
+            Rule::LifeLike(rule) => {
+                let firing = neighbourhood
+                    .iter()
+                    .fold(0, |firing, &state| firing << 1 | (state == 1) as usize);
+                rule.next_state(neighbourhood[4], firing)
+            }
+            Rule::Table(table) => table.next_state(neighbourhood),
+            Rule::LargerThanLife(rule) => {
+                assert_eq!(rule.radius(), 1, "Rules with a range step whole blocks");
+                let firing = neighbourhood.iter().filter(|&&state| state == 1).count();
+                let state = neighbourhood[4];
+                rule.next_state(state, firing - (state == 1) as usize)
+            }
+        }
+    }
+
+    // Advances a square block of cells, given row by row, by one generation, returning
+    // its center with a margin of `radius` cells removed on every side
+    pub fn next_states(&self, cells: &[u8], width: usize) -> Vec<u8> {
+        if let Rule::LargerThanLife(rule) = self {
+            return rule.next_states(cells, width);
+        }
+
+        (1..width - 1)
+            .flat_map(|y| (1..width - 1).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let neighbourhood: [u8; 9] =
+                    std::array::from_fn(|i| cells[(y + i / 3 - 1) * width + x + i % 3 - 1]);
+                self.next_state(&neighbourhood)
+            })
+            .collect()
+    }
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Rule::CONWAY
+    }
+}
+
+impl FromStr for Rule {
+    type Err = RuleParseError;
+
+    // Rule tables are named after their file, so they can't be parsed from a rule string
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.trim().chars();
+        match (chars.next(), chars.next()) {
+            (Some('R' | 'r'), Some(c)) if c.is_ascii_digit() => {
+                Ok(Rule::LargerThanLife(s.trim().parse()?))
+            }
+            _ => Ok(Rule::LifeLike(s.parse()?)),
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rule::LifeLike(rule) => write!(f, "{rule}"),
+            Rule::Table(table) => write!(f, "{table}"),
+            Rule::LargerThanLife(rule) => write!(f, "{rule}"),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct RuleParseError {
+    message: String,
+}
+
+impl RuleParseError {
+    pub(crate) fn new(message: impl Into<String>) -> Self {
+        RuleParseError {
+            message: message.into(),
+        }
+    }
+
+    pub(crate) fn unsupported(rule: &str) -> Self {
+        Self::new(format!("Unsupported rule \"{rule}\""))
+    }
+}
+
+impl fmt::Display for RuleParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for RuleParseError {}
@@ -0,0 +1,366 @@
+use std::{fmt, str::FromStr};
+
+use super::{Rule, RuleParseError};
+
+// Largest supported radius, as in Golly
+const MAX_RADIUS: u32 = 500;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum Range {
+    Moore,
+    VonNeumann,
+}
+
+// Outer totalistic rule counting the live cells within a radius, in Golly's Larger than
+// Life (R5,C0,M1,S34..58,B34..45,NM) or HROT (R2,C0,S2-3,B3,NN) notation. With more
+// than two states cells which fail to survive decay, as in Generations rules.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct LargerThanLife {
+    radius: u32,
+    states: u16,
+    range: Range,
+    // Indexed by the number of live cells in the range, not counting the center
+    birth: Vec<bool>,
+    survival: Vec<bool>,
+    // Larger than Life notation gives whether the center is counted, HROT never counts it
+    middle: Option<bool>,
+}
+
+impl LargerThanLife {
+    pub fn radius(&self) -> u32 {
+        self.radius
+    }
+
+    pub fn states(&self) -> u16 {
+        self.states
+    }
+
+    // Cells within the radius of the center, excluding the center itself
+    fn range_size(&self) -> usize {
+        let r = self.radius as usize;
+        match self.range {
+            Range::Moore => (2 * r + 1) * (2 * r + 1) - 1,
+            Range::VonNeumann => 2 * r * (r + 1),
+        }
+    }
+
+    // Half width of the row `dy` rows away from the center
+    fn row_reach(&self, dy: i64) -> i64 {
+        match self.range {
+            Range::Moore => self.radius as i64,
+            Range::VonNeumann => self.radius as i64 - dy.abs(),
+        }
+    }
+
+    pub fn next_state(&self, state: u8, live_neighbours: usize) -> u8 {
+        match state {
+            0 if self.birth[live_neighbours] => 1,
+            0 => 0,
+            1 if self.survival[live_neighbours] => 1,
+            _ => ((state as u16 + 1) % self.states) as u8,
+        }
+    }
+
+    // Advances a square block of cells, given row by row, by one generation. Only the
+    // cells at least `radius` cells away from the edge can be computed, so the result
+    // is the center of the block with that margin removed on every side.
+    pub fn next_states(&self, cells: &[u8], width: usize) -> Vec<u8> {
+        let r = self.radius as usize;
+        debug_assert_eq!(cells.len(), width * width);
+        debug_assert!(width > 2 * r);
+
+        // Live cells to the left of each position in each row
+        let row_sums: Vec<Vec<u32>> = cells
+            .chunks(width)
+            .map(|row| {
+                std::iter::once(0)
+                    .chain(row.iter().scan(0, |sum, &state| {
+                        *sum += (state == 1) as u32;
+                        Some(*sum)
+                    }))
+                    .collect()
+            })
+            .collect();
+
+        let inner = width - 2 * r;
+        let mut states = Vec::with_capacity(inner * inner);
+        for y in r..width - r {
+            for x in r..width - r {
+                let count: u32 = (-(r as i64)..=r as i64)
+                    .map(|dy| {
+                        let reach = self.row_reach(dy);
+                        let row = &row_sums[(y as i64 + dy) as usize];
+                        row[(x as i64 + reach + 1) as usize] - row[(x as i64 - reach) as usize]
+                    })
+                    .sum();
+
+                let state = cells[y * width + x];
+                let live_neighbours = count as usize - (state == 1) as usize;
+                states.push(self.next_state(state, live_neighbours));
+            }
+        }
+        states
+    }
+}
+
+// Parses a number, or a range of numbers separated by `separator`
+fn parse_range(s: &str, separator: &str) -> Option<(usize, usize)> {
+    match s.split_once(separator) {
+        Some((min, max)) => Some((min.parse().ok()?, max.parse().ok()?)),
+        None => s.parse().ok().map(|n| (n, n)),
+    }
+}
+
+fn condition_ranges(conditions: &[bool]) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = vec![];
+    for (count, _) in conditions.iter().enumerate().filter(|(_, &set)| set) {
+        match ranges.last_mut() {
+            Some((_, max)) if *max + 1 == count => *max = count,
+            _ => ranges.push((count, count)),
+        }
+    }
+    ranges
+}
+
+impl FromStr for LargerThanLife {
+    type Err = RuleParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || RuleParseError::unsupported(s);
+
+        let mut radius = None;
+        let mut states = 2;
+        let mut range = Range::Moore;
+        let mut middle = None;
+        let mut birth = vec![];
+        let mut survival = vec![];
+
+        // Conditions may be lists, so numbers without a prefix continue the last S or B
+        let mut current_is_survival = None;
+        let ltl = s.contains("..");
+        let separator = if ltl { ".." } else { "-" };
+
+        for field in s.split(',').map(str::trim) {
+            let (prefix, value) = field.split_at(field.chars().next().map_or(0, |c| {
+                if c.is_ascii_alphabetic() {
+                    c.len_utf8()
+                } else {
+                    0
+                }
+            }));
+
+            match prefix.to_ascii_uppercase().as_str() {
+                "R" => {
+                    radius = Some(value.parse::<u32>().map_err(|_| err())?);
+                    current_is_survival = None;
+                }
+                "C" => {
+                    states = match value.parse::<u16>().map_err(|_| err())? {
+                        0..=2 => 2,
+                        states => states,
+                    };
+                    current_is_survival = None;
+                }
+                "M" => {
+                    middle = Some(match value {
+                        "0" => false,
+                        "1" => true,
+                        _ => return Err(err()),
+                    });
+                    current_is_survival = None;
+                }
+                "N" => {
+                    range = match value.to_ascii_uppercase().as_str() {
+                        "M" => Range::Moore,
+                        "N" => Range::VonNeumann,
+                        _ => return Err(err()),
+                    };
+                    current_is_survival = None;
+                }
+                "S" | "B" | "" => {
+                    let is_survival = match prefix {
+                        "" => current_is_survival.ok_or_else(err)?,
+                        prefix => prefix.eq_ignore_ascii_case("S"),
+                    };
+                    let conditions = if is_survival {
+                        &mut survival
+                    } else {
+                        &mut birth
+                    };
+                    if !value.is_empty() {
+                        conditions.push(parse_range(value, separator).ok_or_else(err)?);
+                    }
+                    current_is_survival = Some(is_survival);
+                }
+                _ => return Err(err()),
+            }
+        }
+
+        let radius = radius
+            .filter(|radius| (1..=MAX_RADIUS).contains(radius))
+            .ok_or_else(err)?;
+        if states > Rule::MAX_STATES || (ltl && middle.is_none()) {
+            return Err(err());
+        }
+
+        let mut rule = LargerThanLife {
+            radius,
+            states,
+            range,
+            birth: vec![],
+            survival: vec![],
+            middle,
+        };
+
+        let size = rule.range_size();
+        let conditions = |ranges: &[(usize, usize)], offset: usize| {
+            let mut conditions = vec![false; size + 1];
+            for &(min, max) in ranges {
+                if min > max || max > size + offset || max < offset {
+                    return None;
+                }
+                for count in min.max(offset)..=max {
+                    conditions[count - offset] = true;
+                }
+            }
+            Some(conditions)
+        };
+
+        // When the middle is counted a surviving cell counts itself
+        rule.birth = conditions(&birth, 0).ok_or_else(err)?;
+        rule.survival = conditions(&survival, middle.unwrap_or(false) as usize).ok_or_else(err)?;
+
+        // Empty space would turn alive, which can't be emulated for rules with a range
+        if rule.birth[0] {
+            return Err(RuleParseError::new(format!(
+                "B0 is not supported for rules with a range, \"{s}\""
+            )));
+        }
+
+        Ok(rule)
+    }
+}
+
+impl fmt::Display for LargerThanLife {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let states = match self.states {
+            2 => 0,
+            states => states,
+        };
+        let range = match self.range {
+            Range::Moore => "M",
+            Range::VonNeumann => "N",
+        };
+
+        let birth = condition_ranges(&self.birth);
+        let survival = condition_ranges(&self.survival);
+
+        // Larger than Life notation only has a single range for each condition
+        if let (Some(middle), [(birth_min, birth_max)], [(survival_min, survival_max)]) =
+            (self.middle, &birth[..], &survival[..])
+        {
+            let offset = middle as usize;
+            return write!(
+                f,
+                "R{},C{states},M{},S{}..{},B{birth_min}..{birth_max},N{range}",
+                self.radius,
+                offset,
+                survival_min + offset,
+                survival_max + offset,
+            );
+        }
+
+        let list = |ranges: &[(usize, usize)]| {
+            ranges
+                .iter()
+                .map(|&(min, max)| match min == max {
+                    true => min.to_string(),
+                    false => format!("{min}-{max}"),
+                })
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        write!(
+            f,
+            "R{},C{states},S{},B{},N{range}",
+            self.radius,
+            list(&survival),
+            list(&birth)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::life::{
+        rule::Topology,
+        tests::{assert_steps_match_naive, soup},
+        Life,
+    };
+
+    #[test]
+    fn parses_both_notations() {
+        let bosco: LargerThanLife = "R5,C0,M1,S34..58,B34..45,NM".parse().unwrap();
+        assert_eq!(bosco.radius(), 5);
+        assert_eq!(bosco.to_string(), "R5,C0,M1,S34..58,B34..45,NM");
+        assert!(bosco.survival[33] && bosco.survival[57] && !bosco.survival[58]);
+
+        for rule in [
+            "R2,C0,S2-3,5,B3,NN",
+            "R3,C4,S,B5-9,14,NM",
+            "R1,C0,S2-3,B3,NM",
+        ] {
+            let parsed: LargerThanLife = rule.parse().unwrap();
+            assert_eq!(parsed.to_string(), rule);
+        }
+
+        for rule in [
+            "R0,C0,S2,B3,NM",
+            "R2,C0,S2,B3,NX",
+            "R2,C0,S2..3,B3..3,NM",
+            "R2,C0,B0,NM",
+        ] {
+            assert!(rule.parse::<LargerThanLife>().is_err());
+        }
+    }
+
+    #[test]
+    fn blocks_lose_their_margin() {
+        let rule: LargerThanLife = "R2,C0,S,B2,NN".parse().unwrap();
+        let mut cells = vec![0; 36];
+        cells[6 * 2 + 2] = 1;
+        cells[6 * 4 + 2] = 1;
+
+        // Only the cells between the two live cells are within range of both
+        let next = rule.next_states(&cells, 6);
+        assert_eq!(next, vec![0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn larger_than_life_rules_match_naive_simulation() {
+        let rules = [
+            "R2,C0,S2-5,B3-4,NN",
+            "R3,C3,S6-12,B9-11,NM",
+            "R5,C0,M1,S34..58,B34..45,NM",
+        ];
+        for rule in rules {
+            let mut life = Life::from_cell_positions(soup(24));
+            life.set_rule(rule.parse().unwrap()).unwrap();
+            assert_steps_match_naive(&mut life, &[1, 1, 2, 4, 8, 16]);
+            assert!(life.root.alive() > 0, "{rule}");
+        }
+    }
+
+    #[test]
+    fn larger_than_life_rules_wrap_around_a_torus() {
+        let mut life = Life::from_cell_positions(soup(10));
+        life.set_rule("R3,C0,S6-12,B9-11,NM".parse().unwrap())
+            .unwrap();
+        life.set_topology(Topology::Torus {
+            width: 12,
+            height: 10,
+        });
+        assert_steps_match_naive(&mut life, &[1; 20]);
+    }
+}
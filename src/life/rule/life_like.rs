@@ -0,0 +1,716 @@
+use std::{fmt, str::FromStr};
+
+use super::{
+    neighbourhood::{Neighbourhood, E, N, NE, NW, S, SE, SW, W},
+    Rule, RuleParseError,
+};
+
+// Bit of each ring neighbour within a neighbourhood index
+const RING_BITS: [u32; 8] = [7, 6, 3, 0, 1, 2, 5, 8];
+const CENTER_BIT: u32 = 4;
+
+// Letters of each neighbour count, in canonical order
+const HENSEL_LETTERS: [&str; 9] = [
+    "",
+    "ce",
+    "cekain",
+    "cekainyqjr",
+    "cekainyqjrtwz",
+    "cekainyqjr",
+    "cekain",
+    "ce",
+    "",
+];
+
+// One configuration of each letter, counts above 4 use the complement of 8 - count
+const HENSEL_REPRESENTATIVES: [(char, u8); 31] = [
+    ('c', NE),
+    ('e', N),
+    ('c', NE | SE),
+    ('e', N | E),
+    ('k', N | SE),
+    ('a', N | NE),
+    ('i', N | S),
+    ('n', NE | SW),
+    ('c', NE | SE | SW),
+    ('e', N | E | S),
+    ('k', N | E | SW),
+    ('a', N | NE | E),
+    ('i', NW | N | NE),
+    ('n', N | NE | SE),
+    ('y', N | SE | SW),
+    ('q', N | NE | SW),
+    ('j', N | NE | W),
+    ('r', N | NE | S),
+    ('c', NE | SE | SW | NW),
+    ('e', N | E | S | W),
+    ('k', N | NE | SE | W),
+    ('a', N | NE | E | SE),
+    ('i', N | NE | SE | S),
+    ('n', N | NE | SE | NW),
+    ('y', N | NE | SE | SW),
+    ('q', N | NE | E | SW),
+    ('j', N | NE | S | W),
+    ('r', N | NE | E | S),
+    ('t', NW | N | NE | S),
+    ('w', N | NE | SW | W),
+    ('z', N | NE | S | SW),
+];
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// Rule on the 3x3 Moore neighbourhood, covering outer totalistic,
+// isotropic non-totalistic (Hensel) and arbitrary MAP rules, as well as outer
+// totalistic rules on smaller neighbourhoods. With more than two states it is a
+// Generations rule, where cells that fail to survive decay through the remaining
+// states instead of dying.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct LifeLike {
+    // Bit n is set when a cell with neighbourhood n is firing in the next generation.
+    // The neighbourhood of firing cells is read row by row with the north west cell
+    // as the highest bit.
+    transitions: [u64; 8],
+    states: u16,
+    neighbourhood: Neighbourhood,
+}
+
+impl LifeLike {
+    pub const CONWAY: LifeLike = LifeLike::outer_totalistic(1 << 3, 1 << 2 | 1 << 3);
+
+    // Bit n of each mask is set when n live neighbours trigger it
+    pub const fn outer_totalistic(birth: u16, survival: u16) -> Self {
+        let mut transitions = [0; 8];
+        let mut neighbourhood = 0;
+        while neighbourhood < 512 {
+            let alive = neighbourhood >> CENTER_BIT & 1 == 1;
+            let surrounding = (neighbourhood as u32).count_ones() - alive as u32;
+            let mask = if alive { survival } else { birth };
+            if mask & (1 << surrounding) != 0 {
+                transitions[neighbourhood / 64] |= 1 << (neighbourhood % 64);
+            }
+            neighbourhood += 1;
+        }
+        LifeLike {
+            transitions,
+            states: 2,
+            neighbourhood: Neighbourhood::MOORE,
+        }
+    }
+
+    // Outer totalistic rule which only counts the cells in the neighbourhood
+    pub fn totalistic(neighbourhood: Neighbourhood, birth: u16, survival: u16) -> Self {
+        LifeLike {
+            neighbourhood,
+            ..LifeLike::from_fn(|n| {
+                let (alive, ring) = ring_of_neighbourhood(n);
+                let count = (ring & neighbourhood.ring()).count_ones();
+                let mask = if alive { survival } else { birth };
+                mask & (1 << count) != 0
+            })
+        }
+    }
+
+    fn from_fn(f: impl Fn(usize) -> bool) -> Self {
+        let mut transitions = [0; 8];
+        for neighbourhood in (0..512).filter(|&n| f(n)) {
+            transitions[neighbourhood / 64] |= 1 << (neighbourhood % 64);
+        }
+        LifeLike {
+            transitions,
+            states: 2,
+            neighbourhood: Neighbourhood::MOORE,
+        }
+    }
+
+    pub fn states(&self) -> u16 {
+        self.states
+    }
+
+    pub fn neighbourhood(&self) -> Neighbourhood {
+        self.neighbourhood
+    }
+
+    // `neighbourhood` only holds the cells in the firing state
+    pub fn next_state(&self, state: u8, neighbourhood: usize) -> u8 {
+        match state {
+            0 | 1 if self.fires(neighbourhood) => 1,
+            0 => 0,
+            _ => ((state as u16 + 1) % self.states) as u8,
+        }
+    }
+
+    fn fires(&self, neighbourhood: usize) -> bool {
+        self.transitions[neighbourhood / 64] & (1 << (neighbourhood % 64)) != 0
+    }
+
+    // Empty space turns alive, see `Rule::b0_emulation`
+    pub fn has_b0(&self) -> bool {
+        self.fires(0)
+    }
+
+    // A live cell surrounded by live cells survives
+    pub fn has_s8(&self) -> bool {
+        self.fires(511)
+    }
+
+    // The same rule applied to a universe with every cell inverted
+    pub fn with_inverted_inputs(&self) -> Self {
+        LifeLike {
+            transitions: LifeLike::from_fn(|n| self.fires(!n & 511)).transitions,
+            ..*self
+        }
+    }
+
+    pub fn with_inverted_output(&self) -> Self {
+        LifeLike {
+            transitions: LifeLike::from_fn(|n| !self.fires(n)).transitions,
+            ..*self
+        }
+    }
+
+    // Birth and survival masks of two state rules which only depend on the number of
+    // live cells in their neighbourhood, with bit n set when n live cells trigger it
+    pub fn count_conditions(&self) -> Option<(u16, u16)> {
+        if self.states != 2 {
+            return None;
+        }
+
+        let neighbours: Vec<u8> = (0..8)
+            .map(|i| 1 << i)
+            .filter(|&bit| self.neighbourhood.ring() & bit != 0)
+            .collect();
+        let mask = |alive| {
+            (0..=neighbours.len())
+                .filter(|&count| self.fires_with_ring(alive, neighbours[..count].iter().sum()))
+                .fold(0u16, |mask, count| mask | 1 << count)
+        };
+        let (birth, survival) = (mask(false), mask(true));
+
+        let counts_only = (0..512).all(|n| {
+            let (alive, ring) = ring_of_neighbourhood(n);
+            let count = (ring & self.neighbourhood.ring()).count_ones();
+            let mask = if alive { survival } else { birth };
+            self.fires(n) == (mask >> count & 1 == 1)
+        });
+        counts_only.then_some((birth, survival))
+    }
+
+    fn fires_with_ring(&self, alive: bool, ring: u8) -> bool {
+        self.fires(neighbourhood_of_ring(alive, ring))
+    }
+
+    fn is_isotropic(&self) -> bool {
+        (0..512).all(|n| {
+            let (alive, ring) = ring_of_neighbourhood(n);
+            let (count, letter) = hensel_letter(ring);
+            let representative = ring_representative(count, letter);
+            self.fires(n) == self.fires_with_ring(alive, representative)
+        })
+    }
+
+    // Neighbour counts triggering a totalistic rule, counting neighbours in ring order
+    fn totalistic_conditions(&self, alive: bool) -> String {
+        let neighbours: Vec<u8> = (0..8)
+            .map(|i| 1 << i)
+            .filter(|&bit| self.neighbourhood.ring() & bit != 0)
+            .collect();
+
+        (0..=neighbours.len())
+            .filter(|&count| self.fires_with_ring(alive, neighbours[..count].iter().sum()))
+            .map(|count| count.to_string())
+            .collect()
+    }
+
+    fn hensel_conditions(&self, alive: bool) -> String {
+        let mut conditions = String::new();
+
+        for (count, letters) in HENSEL_LETTERS.iter().enumerate() {
+            let count = count as u32;
+            if letters.is_empty() {
+                if self.fires_with_ring(alive, ring_representative(count, None)) {
+                    conditions += &count.to_string();
+                }
+                continue;
+            }
+
+            let (included, excluded): (String, String) = letters.chars().partition(|&letter| {
+                self.fires_with_ring(alive, ring_representative(count, Some(letter)))
+            });
+
+            if included.is_empty() {
+                continue;
+            }
+
+            conditions += &count.to_string();
+            if excluded.is_empty() {
+                continue;
+            }
+            if included.len() <= excluded.len() {
+                conditions += &included;
+            } else {
+                conditions += "-";
+                conditions += &excluded;
+            }
+        }
+
+        conditions
+    }
+
+    fn to_map(self) -> String {
+        let bits: Vec<bool> = (0..512).map(|n| self.fires(n)).collect();
+        let encoded: String = bits
+            .chunks(6)
+            .map(|chunk| {
+                let value = chunk
+                    .iter()
+                    .enumerate()
+                    .fold(0, |value, (i, &bit)| value | (bit as usize) << (5 - i));
+                BASE64[value] as char
+            })
+            .collect();
+        format!("MAP{encoded}")
+    }
+
+    fn from_map(map: &str) -> Option<Self> {
+        let mut bits = Vec::with_capacity(516);
+        for c in map.bytes() {
+            let value = BASE64.iter().position(|&b| b == c)?;
+            bits.extend((0..6).rev().map(|i| value >> i & 1 == 1));
+        }
+
+        if bits.len() < 512 || bits.len() > 516 {
+            return None;
+        }
+
+        Some(LifeLike::from_fn(|n| bits[n]))
+    }
+}
+
+impl Default for LifeLike {
+    fn default() -> Self {
+        LifeLike::CONWAY
+    }
+}
+
+fn ring_of_neighbourhood(neighbourhood: usize) -> (bool, u8) {
+    let ring = RING_BITS
+        .iter()
+        .enumerate()
+        .filter(|(_, &bit)| neighbourhood >> bit & 1 == 1)
+        .fold(0, |ring, (i, _)| ring | 1 << i);
+    (neighbourhood >> CENTER_BIT & 1 == 1, ring)
+}
+
+fn neighbourhood_of_ring(alive: bool, ring: u8) -> usize {
+    RING_BITS
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| ring >> i & 1 == 1)
+        .fold((alive as usize) << CENTER_BIT, |n, (_, bit)| n | 1 << bit)
+}
+
+// Smallest ring among the rotations and reflections of the given ring
+fn canonical_ring(ring: u8) -> u8 {
+    let mirrored = (0..8)
+        .filter(|i| ring >> i & 1 == 1)
+        .fold(0u8, |mirrored, i| mirrored | 1 << ((8 - i) % 8));
+
+    [ring, mirrored]
+        .iter()
+        .flat_map(|&ring| (0..4).map(move |quarter_turns| ring.rotate_left(quarter_turns * 2)))
+        .min()
+        .unwrap()
+}
+
+fn hensel_letter(ring: u8) -> (u32, Option<char>) {
+    let count = ring.count_ones();
+    if HENSEL_LETTERS[count as usize].is_empty() {
+        return (count, None);
+    }
+
+    let canonical = canonical_ring(if count > 4 { !ring } else { ring });
+    let letter = HENSEL_REPRESENTATIVES
+        .iter()
+        .find(|(_, representative)| {
+            representative.count_ones() == count.min(8 - count)
+                && canonical_ring(*representative) == canonical
+        })
+        .map(|(letter, _)| *letter);
+
+    (count, letter)
+}
+
+fn ring_representative(count: u32, letter: Option<char>) -> u8 {
+    let Some(letter) = letter else {
+        return ((1u16 << count) - 1) as u8;
+    };
+
+    let (_, representative) = HENSEL_REPRESENTATIVES
+        .iter()
+        .find(|(l, representative)| {
+            *l == letter && representative.count_ones() == count.min(8 - count)
+        })
+        .unwrap();
+
+    if count > 4 {
+        !representative
+    } else {
+        *representative
+    }
+}
+
+// Parses a list of Hensel conditions (2-a3ij4) into the set of rings it accepts
+fn parse_conditions(conditions: &str) -> Option<[bool; 256]> {
+    let mut accepted = [false; 256];
+    let mut chars = conditions.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let count = c.to_digit(10).filter(|&count| count <= 8)?;
+        let letters = HENSEL_LETTERS[count as usize];
+
+        let negated = chars.next_if_eq(&'-').is_some();
+        let mut listed = String::new();
+        while let Some(letter) = chars.next_if(|c| c.is_ascii_lowercase()) {
+            if !letters.contains(letter) {
+                return None;
+            }
+            listed.push(letter);
+        }
+        if negated && listed.is_empty() {
+            return None;
+        }
+
+        for ring in (0..=255u8).filter(|ring| ring.count_ones() == count) {
+            let accepts = match hensel_letter(ring).1 {
+                Some(letter) if !listed.is_empty() => listed.contains(letter) != negated,
+                _ => true,
+            };
+            accepted[ring as usize] |= accepts;
+        }
+    }
+
+    Some(accepted)
+}
+
+fn strip_prefix(part: &str, prefix: char) -> Option<&str> {
+    part.strip_prefix(prefix)
+        .or_else(|| part.strip_prefix(prefix.to_ascii_lowercase()))
+}
+
+// Parses a list of neighbour counts (234) into a mask with bit n set for each count n
+fn parse_counts(counts: &str, neighbourhood: Neighbourhood) -> Option<u16> {
+    counts.chars().try_fold(0, |mask, c| {
+        let count = c
+            .to_digit(10)
+            .filter(|&count| count <= neighbourhood.size())?;
+        Some(mask | 1 << count)
+    })
+}
+
+fn parse_life_like(first: &str, second: &str, neighbourhood: Neighbourhood) -> Option<LifeLike> {
+    let (birth, survival) = match (strip_prefix(first, 'B'), strip_prefix(second, 'S')) {
+        (Some(birth), Some(survival)) => (birth, survival),
+        _ => match (strip_prefix(first, 'S'), strip_prefix(second, 'B')) {
+            (Some(survival), Some(birth)) => (birth, survival),
+            _ if (first.chars().chain(second.chars())).all(|c| c.is_ascii_digit()) => {
+                (second, first)
+            }
+            _ => return None,
+        },
+    };
+
+    if neighbourhood != Neighbourhood::MOORE {
+        let birth = parse_counts(birth, neighbourhood)?;
+        let survival = parse_counts(survival, neighbourhood)?;
+        return Some(LifeLike::totalistic(neighbourhood, birth, survival));
+    }
+
+    let birth = parse_conditions(birth)?;
+    let survival = parse_conditions(survival)?;
+
+    Some(LifeLike::from_fn(|n| {
+        let (alive, ring) = ring_of_neighbourhood(n);
+        if alive {
+            survival[ring as usize]
+        } else {
+            birth[ring as usize]
+        }
+    }))
+}
+
+fn parse_states(states: &str) -> Option<u16> {
+    let states = strip_prefix(states, 'C')
+        .or_else(|| strip_prefix(states, 'G'))
+        .unwrap_or(states);
+
+    states
+        .parse()
+        .ok()
+        .filter(|states| (2..=Rule::MAX_STATES).contains(states))
+}
+
+impl FromStr for LifeLike {
+    type Err = RuleParseError;
+
+    // Accepts B/S notation (B3/S23, B2-a/S12), S/B notation (23/3) and MAP strings,
+    // optionally followed by a Generations state count (/2/3, B2/S/C3, MAP.../3).
+    // A trailing H or V selects the hexagonal or von Neumann neighbourhood (B2/S34H).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || RuleParseError::unsupported(s);
+
+        let s = s.trim();
+
+        // Base64 uses '/' itself, so the state count of a MAP rule follows its 86 digits
+        let (rule, states) = if let Some(map) = s.strip_prefix("MAP") {
+            let digits: String = map.chars().take(86).collect();
+            let states = match map[digits.len()..].trim_start_matches('=') {
+                "" => Some(2),
+                states => states.strip_prefix('/').and_then(parse_states),
+            };
+            (LifeLike::from_map(&digits), states)
+        } else {
+            let (s, neighbourhood) = match s.char_indices().last() {
+                Some((i, 'H' | 'h')) => (&s[..i], Neighbourhood::HEXAGONAL),
+                Some((i, 'V' | 'v')) => (&s[..i], Neighbourhood::VON_NEUMANN),
+                _ => (s, Neighbourhood::MOORE),
+            };
+
+            match s.split('/').collect::<Vec<_>>()[..] {
+                [first, second] => (parse_life_like(first, second, neighbourhood), Some(2)),
+                [first, second, states] => (
+                    parse_life_like(first, second, neighbourhood),
+                    parse_states(states),
+                ),
+                _ => (None, None),
+            }
+        };
+
+        let rule = LifeLike {
+            states: states.ok_or_else(err)?,
+            ..rule.ok_or_else(err)?
+        };
+
+        // Decaying cells can't be inverted, so B0 can't be emulated for Generations rules
+        if rule.states > 2 && rule.has_b0() {
+            return Err(RuleParseError::new(format!(
+                "B0 is only supported for two state rules, not \"{s}\""
+            )));
+        }
+        Ok(rule)
+    }
+}
+
+impl fmt::Display for LifeLike {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.neighbourhood != Neighbourhood::MOORE {
+            if let Some(suffix) = self.neighbourhood.suffix() {
+                let birth = self.totalistic_conditions(false);
+                let survival = self.totalistic_conditions(true);
+                return match self.states {
+                    2 => write!(f, "B{birth}/S{survival}{suffix}"),
+                    states => write!(f, "{survival}/{birth}/{states}{suffix}"),
+                };
+            }
+        }
+
+        if !self.is_isotropic() {
+            write!(f, "{}", self.to_map())?;
+            if self.states > 2 {
+                write!(f, "/{}", self.states)?;
+            }
+            return Ok(());
+        }
+
+        let birth = self.hensel_conditions(false);
+        let survival = self.hensel_conditions(true);
+        let totalistic = !(birth.clone() + &survival).contains(char::is_alphabetic);
+
+        match self.states {
+            2 => write!(f, "B{birth}/S{survival}"),
+            states if totalistic => write!(f, "{survival}/{birth}/{states}"),
+            states => write!(f, "B{birth}/S{survival}/C{states}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::life::{
+        rule::Neighbourhood,
+        tests::{assert_steps_match_naive, r_pentomino},
+        Life,
+    };
+
+    #[test]
+    fn parses_both_notations() {
+        assert_eq!("B3/S23".parse(), Ok(LifeLike::CONWAY));
+        assert_eq!("b3/s23".parse(), Ok(LifeLike::CONWAY));
+        assert_eq!("23/3".parse(), Ok(LifeLike::CONWAY));
+        assert_eq!("S23/B3".parse(), Ok(LifeLike::CONWAY));
+        assert_eq!(
+            "B36/S23".parse::<LifeLike>().unwrap().to_string(),
+            "B36/S23"
+        );
+        assert_eq!("B2/S".parse::<LifeLike>().unwrap().to_string(), "B2/S");
+        assert_eq!(
+            "34678/3678".parse::<LifeLike>().unwrap().to_string(),
+            "B3678/S34678"
+        );
+        assert!("B9/S23".parse::<LifeLike>().is_err());
+        assert!("B3S23".parse::<LifeLike>().is_err());
+    }
+
+    #[test]
+    fn parses_generations_rules() {
+        let brians_brain: LifeLike = "/2/3".parse().unwrap();
+        assert_eq!(brians_brain.states(), 3);
+        assert_eq!("B2/S/C3".parse(), Ok(brians_brain));
+        assert_eq!(brians_brain.to_string(), "/2/3");
+
+        let star_wars: LifeLike = "345/2/4".parse().unwrap();
+        assert_eq!(star_wars.to_string(), "345/2/4");
+        assert_eq!(star_wars.next_state(1, 0b111_010_000), 1);
+        assert_eq!(star_wars.next_state(1, 0b000_010_000), 2);
+        assert_eq!(star_wars.next_state(2, 0b111_010_000), 3);
+        assert_eq!(star_wars.next_state(3, 0b111_010_000), 0);
+
+        assert_eq!(
+            "B2a/S12/C5".parse::<LifeLike>().unwrap().to_string(),
+            "B2a/S12/C5"
+        );
+        assert!("B2/S/1".parse::<LifeLike>().is_err());
+        assert!("B02/S/3".parse::<LifeLike>().is_err());
+        assert!("B2/S/257".parse::<LifeLike>().is_err());
+    }
+
+    #[test]
+    fn hensel_letters_cover_every_configuration() {
+        for count in 0..=8u32 {
+            let rings: Vec<u8> = (0..=255u8).filter(|r| r.count_ones() == count).collect();
+            let classes: std::collections::HashSet<u8> =
+                rings.iter().map(|&r| canonical_ring(r)).collect();
+
+            let letters = HENSEL_LETTERS[count as usize];
+            assert_eq!(classes.len(), letters.len().max(1));
+
+            for &ring in &rings {
+                let (_, letter) = hensel_letter(ring);
+                assert_eq!(letter.is_some(), !letters.is_empty());
+                let representative = ring_representative(count, letter);
+                assert_eq!(canonical_ring(representative), canonical_ring(ring));
+            }
+        }
+    }
+
+    #[test]
+    fn isotropic_rules_round_trip() {
+        for rule in ["B2-a/S12", "B3/S23-a4i", "B2ce3aiy/S2-c3-y", "B34e/S2-i34q"] {
+            let parsed: LifeLike = rule.parse().unwrap();
+            assert_eq!(parsed.to_string(), rule);
+        }
+
+        assert_eq!(
+            "B3/S2-e3".parse::<LifeLike>(),
+            "B3/S2ckain3".parse::<LifeLike>()
+        );
+        assert!("B2x/S23".parse::<LifeLike>().is_err());
+        assert!("B2-/S23".parse::<LifeLike>().is_err());
+    }
+
+    #[test]
+    fn parses_neighbourhood_suffixes() {
+        for rule in ["B2/S34H", "B1/S1V", "B/S0123456H", "34/2/3H", "B13/S012V"] {
+            let parsed: LifeLike = rule.parse().unwrap();
+            assert_eq!(parsed.to_string(), rule);
+        }
+
+        let hex: LifeLike = "B2/S34H".parse().unwrap();
+        assert_eq!(hex.neighbourhood(), Neighbourhood::HEXAGONAL);
+        // The north east and south west cells are not neighbours on a hexagonal grid
+        assert_eq!(hex.next_state(0, 0b011_000_000), 0);
+        assert_eq!(hex.next_state(0, 0b110_000_000), 1);
+        assert_eq!(hex.next_state(0, 0b001_000_100), 0);
+        assert_eq!(hex.next_state(0, 0b100_000_001), 1);
+
+        let von_neumann: LifeLike = "B1/S1V".parse().unwrap();
+        assert_eq!(von_neumann.next_state(0, 0b010_000_000), 1);
+        assert_eq!(von_neumann.next_state(0, 0b100_000_000), 0);
+
+        assert!("B7/S23H".parse::<LifeLike>().is_err());
+        assert!("B2a/S23H".parse::<LifeLike>().is_err());
+        assert!("B5/S1V".parse::<LifeLike>().is_err());
+    }
+
+    #[test]
+    fn custom_neighbourhoods_fall_back_to_map() {
+        let offsets = [(-1, 0), (1, 0), (0, -1)];
+        let neighbourhood = Neighbourhood::from_offsets(offsets).unwrap();
+        assert_eq!(neighbourhood.size(), 3);
+        assert_eq!(
+            neighbourhood
+                .offsets()
+                .collect::<std::collections::HashSet<_>>(),
+            offsets.into_iter().collect()
+        );
+        assert!(Neighbourhood::from_offsets([(2, 0)]).is_none());
+
+        let rule = LifeLike::totalistic(neighbourhood, 1 << 1, 1 << 2);
+        assert!(rule.to_string().starts_with("MAP"));
+        assert_eq!(rule.next_state(0, 0b010_000_000), 1);
+        assert_eq!(rule.next_state(0, 0b000_000_010), 0);
+    }
+
+    #[test]
+    fn map_rules_round_trip() {
+        let conway = "MAPARYXfhZofugWaH7oaIDogBZofuhogOiAaIDogIAAgAAWaH7oaIDogGiA6ICAAIAAaIDogIAAgACAAIAAAAAAAA";
+        assert_eq!(LifeLike::CONWAY.to_map(), conway);
+        assert_eq!(conway.parse(), Ok(LifeLike::CONWAY));
+        assert_eq!(format!("{conway}==").parse(), Ok(LifeLike::CONWAY));
+
+        // Only the north east neighbour is relevant, so the rule is not isotropic
+        let anisotropic = LifeLike::from_fn(|n| n >> 6 & 1 == 1);
+        let text = anisotropic.to_string();
+        assert!(text.starts_with("MAP"));
+        assert_eq!(text.parse(), Ok(anisotropic));
+
+        let generations = format!("{text}/4");
+        assert_eq!(
+            generations.parse::<LifeLike>().unwrap().to_string(),
+            generations
+        );
+    }
+
+    #[test]
+    fn rule_is_read_from_rle_header() {
+        let rle = "x = 3, y = 3, rule = B36/S23\n2o$obo$b2o!\n";
+        let mut life = Life::from_rle(rle);
+        assert_eq!(life.rule().to_string(), "B36/S23");
+        assert!(life.to_rle().contains("rule = B36/S23"));
+        assert_steps_match_naive(&mut life, &[1; 50]);
+    }
+
+    #[test]
+    fn isotropic_rules_match_naive_simulation() {
+        let mut life = r_pentomino();
+        life.set_rule("B2-a/S12".parse().unwrap()).unwrap();
+        assert_steps_match_naive(&mut life, &[1; 30]);
+    }
+
+    #[test]
+    fn generations_rules_match_naive_simulation() {
+        let rle = "x = 4, y = 3, rule = 345/2/4\nA2.B$.2AC$2A.A!\n";
+        let mut life = Life::from_rle(rle);
+        assert_eq!(life.rule().states(), 4);
+        assert_eq!(life.root.alive(), 8);
+        assert_steps_match_naive(&mut life, &[1, 4, 16, 64]);
+    }
+
+    #[test]
+    fn hexagonal_rules_match_naive_simulation() {
+        let mut life = r_pentomino();
+        life.set_rule("B2/S34H".parse().unwrap()).unwrap();
+        assert_eq!(life.rule().neighbourhood(), Neighbourhood::HEXAGONAL);
+        assert_steps_match_naive(&mut life, &[1; 50]);
+    }
+}
@@ -0,0 +1,70 @@
+// Neighbour bits of a Hensel ring, running clockwise from north
+pub(super) const N: u8 = 1 << 0;
+pub(super) const NE: u8 = 1 << 1;
+pub(super) const E: u8 = 1 << 2;
+pub(super) const SE: u8 = 1 << 3;
+pub(super) const S: u8 = 1 << 4;
+pub(super) const SW: u8 = 1 << 5;
+pub(super) const W: u8 = 1 << 6;
+pub(super) const NW: u8 = 1 << 7;
+
+// Offset of each ring neighbour from the center
+const RING_OFFSETS: [(i64, i64); 8] = [
+    (0, -1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+];
+
+// The cells surrounding a center which a rule reads, as a set of ring bits
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Neighbourhood(u8);
+
+impl Neighbourhood {
+    pub const MOORE: Neighbourhood = Neighbourhood(0xff);
+    pub const VON_NEUMANN: Neighbourhood = Neighbourhood(N | E | S | W);
+    // Hexagonal grids are drawn with each row shifted half a cell left of the one
+    // above, which leaves the north east and south west cells apart from the center
+    pub const HEXAGONAL: Neighbourhood = Neighbourhood(N | E | SE | S | W | NW);
+
+    // Builds a neighbourhood from the offsets of its cells, which must be within one
+    // cell of the center
+    pub fn from_offsets(offsets: impl IntoIterator<Item = (i64, i64)>) -> Option<Self> {
+        offsets
+            .into_iter()
+            .try_fold(Neighbourhood(0), |neighbourhood, offset| {
+                let i = RING_OFFSETS.iter().position(|&o| o == offset)?;
+                Some(Neighbourhood(neighbourhood.0 | 1 << i))
+            })
+    }
+
+    pub fn offsets(&self) -> impl Iterator<Item = (i64, i64)> + '_ {
+        RING_OFFSETS
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| self.0 >> i & 1 == 1)
+            .map(|(_, &offset)| offset)
+    }
+
+    pub fn size(&self) -> u32 {
+        self.0.count_ones()
+    }
+
+    pub(crate) fn ring(&self) -> u8 {
+        self.0
+    }
+
+    // Golly marks rules on the hexagonal and von Neumann neighbourhoods with a suffix
+    pub(super) fn suffix(&self) -> Option<&'static str> {
+        match *self {
+            Neighbourhood::MOORE => Some(""),
+            Neighbourhood::HEXAGONAL => Some("H"),
+            Neighbourhood::VON_NEUMANN => Some("V"),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,618 @@
+// Golly rule files, holding either a transition table (@TABLE) or a rule tree (@TREE)
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use super::{Neighbourhood, Rule, RuleParseError};
+
+// Set of states, bit n is set when state n is included
+type StateSet = [u64; 4];
+
+fn single_state(state: u8) -> StateSet {
+    let mut set = [0; 4];
+    set[state as usize / 64] |= 1 << (state % 64);
+    set
+}
+
+fn set_contains(set: &StateSet, state: usize) -> bool {
+    set[state / 64] & (1 << (state % 64)) != 0
+}
+
+// Positions within a 3x3 neighbourhood, read row by row from the north west
+const NW: usize = 0;
+const N: usize = 1;
+const NE: usize = 2;
+const W: usize = 3;
+const C: usize = 4;
+const E: usize = 5;
+const SW: usize = 6;
+const S: usize = 7;
+const SE: usize = 8;
+
+// Center followed by the neighbours running clockwise from north, as in a @TABLE line
+fn table_inputs(neighbourhood: Neighbourhood) -> &'static [usize] {
+    match neighbourhood {
+        Neighbourhood::VON_NEUMANN => &[C, N, E, S, W],
+        Neighbourhood::HEXAGONAL => &[C, N, E, SE, S, W, NW],
+        _ => &[C, N, NE, E, SE, S, SW, W, NW],
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+enum Lookup {
+    Table {
+        inputs: &'static [usize],
+        outputs: Vec<u8>,
+        // Bitsets of the transitions accepting each state at each input, indexed by
+        // input, then state, then word
+        masks: Vec<u64>,
+        words: usize,
+    },
+    Tree {
+        inputs: &'static [usize],
+        // Level 1 nodes point at states, higher levels at nodes of the level below
+        nodes: Vec<(u32, Vec<u32>)>,
+    },
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct RuleTable {
+    name: String,
+    states: u16,
+    neighbourhood: Neighbourhood,
+    lookup: Lookup,
+}
+
+impl RuleTable {
+    pub fn parse(rule_file: &str) -> Result<Self, RuleParseError> {
+        let mut name = None;
+        let mut section = "";
+        let mut table = vec![];
+        let mut tree = vec![];
+
+        for line in rule_file.lines() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.starts_with('@') {
+                let mut words = line.split_whitespace();
+                section = words.next().unwrap();
+                if section == "@RULE" {
+                    name = words.next();
+                }
+                continue;
+            }
+
+            match section {
+                "@TABLE" => table.push(line),
+                "@TREE" => tree.push(line),
+                _ => {}
+            }
+        }
+
+        let name = name
+            .ok_or_else(|| RuleParseError::new("Rule file is missing an @RULE name"))?
+            .to_string();
+
+        let (states, neighbourhood, lookup) = match (table.is_empty(), tree.is_empty()) {
+            (false, _) => parse_table(&table)?,
+            (true, false) => parse_tree(&tree)?,
+            (true, true) => {
+                return Err(RuleParseError::new(format!(
+                    "Rule {name} has no @TABLE or @TREE section"
+                )))
+            }
+        };
+
+        let table = RuleTable {
+            name,
+            states,
+            neighbourhood,
+            lookup,
+        };
+
+        // Empty space has to stay empty for the quadtree to stay finite
+        match table.next_state(&[0; 9]) {
+            0 => Ok(table),
+            state => Err(RuleParseError::new(format!(
+                "Rule {table} turns empty space into state {state}"
+            ))),
+        }
+    }
+
+    pub fn states(&self) -> u16 {
+        self.states
+    }
+
+    pub fn neighbourhood(&self) -> Neighbourhood {
+        self.neighbourhood
+    }
+
+    pub fn next_state(&self, neighbourhood: &[u8; 9]) -> u8 {
+        match &self.lookup {
+            Lookup::Table {
+                inputs,
+                outputs,
+                masks,
+                words,
+            } => {
+                let states = self.states as usize;
+                if inputs.iter().any(|&i| neighbourhood[i] as usize >= states) {
+                    return neighbourhood[C];
+                }
+
+                // The first transition accepting every input wins
+                for word in 0..*words {
+                    let accepted = inputs.iter().enumerate().fold(!0, |accepted, (input, &i)| {
+                        let state = neighbourhood[i] as usize;
+                        accepted & masks[(input * states + state) * words + word]
+                    });
+
+                    if accepted != 0 {
+                        return outputs[word * 64 + accepted.trailing_zeros() as usize];
+                    }
+                }
+
+                // Cells without a matching transition keep their state
+                neighbourhood[C]
+            }
+            Lookup::Tree { inputs, nodes } => {
+                let mut node = nodes.len() - 1;
+                for &i in inputs.iter() {
+                    let (level, children) = &nodes[node];
+                    let child = children[neighbourhood[i] as usize];
+                    if *level == 1 {
+                        return child as u8;
+                    }
+                    node = child as usize;
+                }
+                unreachable!("Rule trees end in level 1 nodes")
+            }
+        }
+    }
+}
+
+impl fmt::Display for RuleTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+fn parse_header<'a>(line: &'a str, key: &str, separator: char) -> Option<&'a str> {
+    let (line_key, value) = line.split_once(separator)?;
+    (line_key.trim() == key).then(|| value.trim())
+}
+
+fn parse_state_count(states: &str) -> Result<u16, RuleParseError> {
+    states
+        .parse()
+        .ok()
+        .filter(|states| (2..=Rule::MAX_STATES).contains(states))
+        .ok_or_else(|| RuleParseError::new(format!("Invalid number of states \"{states}\"")))
+}
+
+// Permutations of the neighbours (excluding the center) described by a symmetry
+fn symmetry_permutations(
+    symmetry: &str,
+    neighbours: usize,
+) -> Result<Option<Vec<Vec<usize>>>, RuleParseError> {
+    let err = || RuleParseError::new(format!("Unsupported symmetry \"{symmetry}\""));
+
+    if symmetry == "permute" {
+        return Ok(None);
+    }
+
+    let mirror = |i: usize| (neighbours - i) % neighbours;
+
+    let (rotations, reflect) = match symmetry {
+        "none" => (1, false),
+        "reflect_horizontal" => (1, true),
+        _ => {
+            let rotations = symmetry.strip_prefix("rotate").ok_or_else(err)?;
+            let (rotations, reflect) = match rotations.strip_suffix("reflect") {
+                Some(rotations) => (rotations, true),
+                None => (rotations, false),
+            };
+            let rotations: usize = rotations.parse().map_err(|_| err())?;
+            if rotations < 2 || !neighbours.is_multiple_of(rotations) {
+                return Err(err());
+            }
+            (rotations, reflect)
+        }
+    };
+
+    let step = neighbours / rotations;
+    let mut permutations = vec![];
+    for rotation in 0..rotations {
+        permutations.push(
+            (0..neighbours)
+                .map(|i| (i + rotation * step) % neighbours)
+                .collect(),
+        );
+        if reflect {
+            permutations.push(
+                (0..neighbours)
+                    .map(|i| (mirror(i) + rotation * step) % neighbours)
+                    .collect(),
+            );
+        }
+    }
+
+    Ok(Some(permutations))
+}
+
+// Rearranges the slice into the next lexicographic permutation, returning false once
+// the permutations are exhausted. Equal elements are never swapped, so every distinct
+// arrangement is produced exactly once.
+fn next_permutation<T: Ord>(items: &mut [T]) -> bool {
+    let Some(pivot) = (1..items.len()).rev().find(|&i| items[i - 1] < items[i]) else {
+        return false;
+    };
+    let successor = (pivot..items.len())
+        .rev()
+        .find(|&i| items[i] > items[pivot - 1])
+        .unwrap();
+    items.swap(pivot - 1, successor);
+    items[pivot..].reverse();
+    true
+}
+
+fn parse_table(lines: &[&str]) -> Result<(u16, Neighbourhood, Lookup), RuleParseError> {
+    let mut states = None;
+    let mut neighbourhood = Neighbourhood::MOORE;
+    let mut symmetry = "none";
+    let mut variables: HashMap<&str, Vec<u8>> = HashMap::new();
+    let mut transitions: Vec<(Vec<StateSet>, u8)> = vec![];
+
+    let parse_value =
+        |value: &str, states: u16, variables: &HashMap<&str, Vec<u8>>| match value.parse::<u16>() {
+            Ok(state) if state < states => Ok(vec![state as u8]),
+            Ok(_) => Err(RuleParseError::new(format!(
+                "State {value} is out of range"
+            ))),
+            Err(_) => variables
+                .get(value)
+                .cloned()
+                .ok_or_else(|| RuleParseError::new(format!("Unknown variable \"{value}\""))),
+        };
+
+    for &line in lines {
+        if let Some(value) = parse_header(line, "n_states", ':') {
+            states = Some(parse_state_count(value)?);
+            continue;
+        }
+        if let Some(value) = parse_header(line, "neighborhood", ':') {
+            neighbourhood = match value {
+                "Moore" => Neighbourhood::MOORE,
+                "vonNeumann" => Neighbourhood::VON_NEUMANN,
+                "hexagonal" => Neighbourhood::HEXAGONAL,
+                _ => {
+                    return Err(RuleParseError::new(format!(
+                        "Unsupported neighborhood \"{value}\""
+                    )))
+                }
+            };
+            continue;
+        }
+        if let Some(value) = parse_header(line, "symmetries", ':') {
+            symmetry = value;
+            continue;
+        }
+
+        let states =
+            states.ok_or_else(|| RuleParseError::new("n_states must precede the transitions"))?;
+
+        if let Some(definition) = line.strip_prefix("var ") {
+            let (name, values) = definition
+                .split_once('=')
+                .ok_or_else(|| RuleParseError::new(format!("Invalid variable \"{line}\"")))?;
+            let values = values.trim().trim_start_matches('{').trim_end_matches('}');
+
+            let mut set = vec![];
+            for value in values.split(',') {
+                set.extend(parse_value(value.trim(), states, &variables)?);
+            }
+            variables.insert(name.trim(), set);
+            continue;
+        }
+
+        let inputs = table_inputs(neighbourhood).len();
+        let values: Vec<&str> = if line.contains(',') {
+            line.split(',').map(str::trim).collect()
+        } else {
+            line.split("").filter(|c| !c.trim().is_empty()).collect()
+        };
+        if values.len() != inputs + 1 {
+            return Err(RuleParseError::new(format!(
+                "Transition \"{line}\" should have {} entries",
+                inputs + 1
+            )));
+        }
+
+        for value in &values {
+            parse_value(value, states, &variables)?;
+        }
+
+        // Variables used more than once, or as the output, take the same value everywhere
+        let mut bound: Vec<&str> = vec![];
+        for (i, value) in values.iter().enumerate() {
+            let repeated = values[i + 1..].contains(value) || i == inputs;
+            if variables.contains_key(value) && repeated && !bound.contains(value) {
+                bound.push(value);
+            }
+        }
+        if let Some(output) = bound.iter().find(|&&b| !values[..inputs].contains(&b)) {
+            return Err(RuleParseError::new(format!(
+                "Output variable \"{output}\" is not bound by an input"
+            )));
+        }
+
+        let mut assignment = vec![0; bound.len()];
+        loop {
+            let resolve = |value: &str| match bound.iter().position(|&b| b == value) {
+                Some(b) => vec![variables[value][assignment[b]]],
+                None => parse_value(value, states, &variables).unwrap(),
+            };
+
+            let input_sets: Vec<StateSet> = values[..inputs]
+                .iter()
+                .map(|value| {
+                    resolve(value).iter().fold([0; 4], |set, &state| {
+                        let single = single_state(state);
+                        [0, 1, 2, 3].map(|i| set[i] | single[i])
+                    })
+                })
+                .collect();
+            let output = resolve(values[inputs])[0];
+
+            transitions.extend(
+                symmetric_variants(&input_sets, symmetry)?
+                    .into_iter()
+                    .map(|inputs| (inputs, output)),
+            );
+
+            // Step through every combination of values of the bound variables
+            let Some(b) = (0..bound.len()).find(|&b| assignment[b] + 1 < variables[bound[b]].len())
+            else {
+                break;
+            };
+            assignment[b] += 1;
+            assignment[..b].fill(0);
+        }
+    }
+
+    let states = states.ok_or_else(|| RuleParseError::new("@TABLE is missing n_states"))?;
+    let inputs = table_inputs(neighbourhood);
+    let words = transitions.len().div_ceil(64).max(1);
+
+    let mut masks = vec![0; inputs.len() * states as usize * words];
+    for (t, (sets, _)) in transitions.iter().enumerate() {
+        for (input, set) in sets.iter().enumerate() {
+            for state in (0..states as usize).filter(|&state| set_contains(set, state)) {
+                masks[(input * states as usize + state) * words + t / 64] |= 1 << (t % 64);
+            }
+        }
+    }
+
+    let lookup = Lookup::Table {
+        inputs,
+        outputs: transitions.iter().map(|(_, output)| *output).collect(),
+        masks,
+        words,
+    };
+    Ok((states, neighbourhood, lookup))
+}
+
+fn symmetric_variants(
+    inputs: &[StateSet],
+    symmetry: &str,
+) -> Result<Vec<Vec<StateSet>>, RuleParseError> {
+    let (center, neighbours) = inputs.split_first().unwrap();
+    let mut variants = vec![];
+
+    match symmetry_permutations(symmetry, neighbours.len())? {
+        Some(permutations) => {
+            let mut seen = HashSet::new();
+            for permutation in permutations {
+                let variant: Vec<StateSet> = std::iter::once(*center)
+                    .chain(permutation.iter().map(|&i| neighbours[i]))
+                    .collect();
+                if seen.insert(variant.clone()) {
+                    variants.push(variant);
+                }
+            }
+        }
+        None => {
+            let mut neighbours = neighbours.to_vec();
+            neighbours.sort();
+            loop {
+                variants.push(
+                    std::iter::once(*center)
+                        .chain(neighbours.iter().copied())
+                        .collect(),
+                );
+                if !next_permutation(&mut neighbours) {
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok(variants)
+}
+
+fn parse_tree(lines: &[&str]) -> Result<(u16, Neighbourhood, Lookup), RuleParseError> {
+    let mut states = None;
+    let mut inputs = None;
+    let mut nodes: Vec<(u32, Vec<u32>)> = vec![];
+
+    let err = |line: &str| RuleParseError::new(format!("Invalid @TREE line \"{line}\""));
+
+    for &line in lines {
+        if let Some(value) = parse_header(line, "num_states", '=') {
+            states = Some(parse_state_count(value)?);
+            continue;
+        }
+        if let Some(value) = parse_header(line, "num_neighbors", '=') {
+            // Trees are indexed in the order NW, NE, SW, SE, N, W, E, S, C
+            inputs = Some(match value {
+                "8" => &[NW, NE, SW, SE, N, W, E, S, C][..],
+                "4" => &[N, W, E, S, C][..],
+                _ => return Err(err(line)),
+            });
+            continue;
+        }
+        if parse_header(line, "num_nodes", '=').is_some() {
+            continue;
+        }
+
+        let states = states.ok_or_else(|| err(line))?;
+        let numbers = line
+            .split_whitespace()
+            .map(|n| n.parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| err(line))?;
+
+        let (&level, children) = numbers.split_first().ok_or_else(|| err(line))?;
+        let valid_child = |&child: &u32| match level {
+            1 => child < states as u32,
+            _ => nodes
+                .get(child as usize)
+                .is_some_and(|(l, _)| *l == level - 1),
+        };
+        if level == 0 || children.len() != states as usize || !children.iter().all(valid_child) {
+            return Err(err(line));
+        }
+
+        nodes.push((level, children.to_vec()));
+    }
+
+    let states = states.ok_or_else(|| RuleParseError::new("@TREE is missing num_states"))?;
+    let inputs = inputs.ok_or_else(|| RuleParseError::new("@TREE is missing num_neighbors"))?;
+
+    match nodes.last() {
+        Some((level, _)) if *level as usize == inputs.len() => {}
+        _ => return Err(RuleParseError::new("@TREE root has the wrong level")),
+    }
+
+    let neighbourhood = match inputs.len() {
+        5 => Neighbourhood::VON_NEUMANN,
+        _ => Neighbourhood::MOORE,
+    };
+    Ok((states, neighbourhood, Lookup::Tree { inputs, nodes }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::life::{pack_unpack::CellBounds, tests::assert_steps_match_naive, Life};
+
+    const WIREWORLD: &str = "@RULE WireWorld
+# Electron heads become tails, tails become wire and wire carrying one or
+# two heads next to it becomes a head
+@TABLE
+n_states:4
+neighborhood:Moore
+symmetries:permute
+var a={0,1,2,3}
+var b={a}
+var c={a}
+var d={a}
+var e={a}
+var f={a}
+var g={a}
+var h={a}
+var i={0,2,3}
+var j={i}
+var k={i}
+var l={i}
+var m={i}
+var n={i}
+var o={i}
+1,a,b,c,d,e,f,g,h,2
+2,a,b,c,d,e,f,g,h,3
+3,1,i,j,k,l,m,n,o,1
+3,1,1,i,j,k,l,m,n,1
+@COLORS
+1 255 128 0
+";
+
+    #[test]
+    fn parses_wireworld_table() {
+        let rule = RuleTable::parse(WIREWORLD).unwrap();
+        assert_eq!(rule.to_string(), "WireWorld");
+        assert_eq!(rule.states(), 4);
+
+        assert_eq!(rule.next_state(&[0, 0, 0, 0, 1, 0, 0, 0, 0]), 2);
+        assert_eq!(rule.next_state(&[3, 3, 3, 0, 2, 0, 0, 0, 0]), 3);
+        assert_eq!(rule.next_state(&[0, 0, 1, 0, 3, 0, 0, 0, 0]), 1);
+        assert_eq!(rule.next_state(&[1, 0, 0, 0, 3, 0, 0, 0, 1]), 1);
+        assert_eq!(rule.next_state(&[1, 1, 1, 0, 3, 0, 0, 0, 0]), 3);
+        assert_eq!(rule.next_state(&[1, 1, 1, 0, 0, 0, 0, 0, 0]), 0);
+    }
+
+    #[test]
+    fn bound_variables_and_symmetries() {
+        let rule = RuleTable::parse(
+            "@RULE Copy\n@TABLE\nn_states:3\nneighborhood:vonNeumann\nsymmetries:rotate4\n\
+             var a={1,2}\n0,a,0,0,0,a\n0,a,a,0,0,a\n",
+        )
+        .unwrap();
+
+        // Neighbourhoods are read row by row: NW, N, NE, W, C, E, SW, S, SE
+        assert_eq!(rule.next_state(&[0, 2, 0, 0, 0, 0, 0, 0, 0]), 2);
+        assert_eq!(rule.next_state(&[0, 0, 0, 0, 0, 0, 0, 1, 0]), 1);
+        assert_eq!(rule.next_state(&[0, 1, 0, 0, 0, 2, 0, 0, 0]), 0);
+        assert_eq!(rule.next_state(&[0, 0, 0, 0, 0, 1, 0, 1, 0]), 1);
+        assert_eq!(rule.next_state(&[0, 0, 0, 2, 0, 0, 0, 2, 0]), 2);
+        assert_eq!(rule.next_state(&[0, 2, 0, 0, 0, 0, 0, 2, 0]), 0);
+
+        assert!(RuleTable::parse("@RULE Bad\n@TABLE\nn_states:2\n0,0,0,0,0,0,0,0,0,a\n").is_err());
+        assert!(RuleTable::parse(
+            "@RULE Bad\n@TABLE\nn_states:2\nvar a={0,1}\n0,0,0,0,0,0,0,0,0,a\n"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn empty_space_must_stay_empty() {
+        let rule = "@RULE Fill\n@TABLE\nn_states:2\nneighborhood:vonNeumann\n0,0,0,0,0,1\n";
+        assert!(RuleTable::parse(rule).is_err());
+    }
+
+    #[test]
+    fn parses_rule_tree() {
+        // Every cell copies its northern neighbour
+        let rule = RuleTable::parse(
+            "@RULE Down\n@TREE\nnum_states=2\nnum_neighbors=4\nnum_nodes=9\n\
+             1 0 0\n2 0 0\n3 1 1\n4 2 2\n1 1 1\n2 4 4\n3 5 5\n4 6 6\n5 3 7\n",
+        )
+        .unwrap();
+
+        assert_eq!(rule.next_state(&[0, 1, 0, 0, 0, 0, 0, 0, 0]), 1);
+        assert_eq!(rule.next_state(&[0, 0, 0, 1, 1, 1, 0, 1, 0]), 0);
+        assert!(
+            RuleTable::parse("@RULE Down\n@TREE\nnum_states=2\nnum_neighbors=4\n1 0 2\n").is_err()
+        );
+    }
+
+    #[test]
+    fn rule_tables_match_naive_simulation() {
+        let rule = Rule::from_rule_file(WIREWORLD).unwrap();
+        assert_eq!(rule.to_string(), "WireWorld");
+
+        // An electron travelling along a wire which splits in two
+        let rle = "x = 12, y = 3, rule = WireWorld\n9.2C$BA7C$9.2C!\n";
+        let mut life = Life::from_rle_with_rule(rle, rule);
+        assert_steps_match_naive(&mut life, &[1, 8]);
+
+        let states = life.cell_states(CellBounds::ALL);
+        let heads = states.iter().filter(|(_, state)| *state == 1).count();
+        assert_eq!(heads, 2);
+        assert_steps_match_naive(&mut life, &[2]);
+    }
+}
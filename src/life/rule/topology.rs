@@ -0,0 +1,210 @@
+use std::{fmt, str::FromStr};
+
+use super::RuleParseError;
+
+// The pair of edges which is joined with a twist on a Klein bottle
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum TwistedEdges {
+    TopBottom,
+    LeftRight,
+}
+
+// Shape of the universe, given by a Golly rule suffix such as :T30,20
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum Topology {
+    #[default]
+    Infinite,
+    Plane {
+        width: u32,
+        height: u32,
+    },
+    Torus {
+        width: u32,
+        height: u32,
+    },
+    KleinBottle {
+        width: u32,
+        height: u32,
+        twisted: TwistedEdges,
+    },
+    Sphere {
+        size: u32,
+    },
+}
+
+impl Topology {
+    pub fn size(&self) -> Option<(u32, u32)> {
+        match *self {
+            Topology::Infinite => None,
+            Topology::Plane { width, height }
+            | Topology::Torus { width, height }
+            | Topology::KleinBottle { width, height, .. } => Some((width, height)),
+            Topology::Sphere { size } => Some((size, size)),
+        }
+    }
+
+    // Maps a position outside the grid onto the cell it is joined to, both given
+    // relative to the grid's top left corner. Cells beyond plane edges are always dead.
+    pub fn wrap(&self, x: i64, y: i64) -> Option<(i64, i64)> {
+        let (width, height) = self.size()?;
+        let (width, height) = (width as i64, height as i64);
+        let outside_x = !(0..width).contains(&x);
+        let outside_y = !(0..height).contains(&y);
+
+        match *self {
+            Topology::Infinite | Topology::Plane { .. } => None,
+            Topology::Torus { .. } => Some((x.rem_euclid(width), y.rem_euclid(height))),
+            Topology::KleinBottle { twisted, .. } => {
+                let (x, y) = match twisted {
+                    TwistedEdges::TopBottom if outside_y => (width - 1 - x, y),
+                    TwistedEdges::LeftRight if outside_x => (x, height - 1 - y),
+                    _ => (x, y),
+                };
+                Some((x.rem_euclid(width), y.rem_euclid(height)))
+            }
+            // The top edge is joined to the left edge, and the bottom edge to the right edge
+            Topology::Sphere { .. } => match (outside_x, outside_y) {
+                (true, false) if x < 0 => Some((y, -x - 1)),
+                (true, false) => Some((y, height - 1 - (x - width))),
+                (false, true) if y < 0 => Some((-y - 1, x)),
+                (false, true) => Some((width - 1 - (y - height), x)),
+                _ if (x, y) == (width, -1) => Some((0, height - 1)),
+                _ if (x, y) == (-1, height) => Some((width - 1, 0)),
+                _ => None,
+            },
+        }
+    }
+}
+
+impl FromStr for Topology {
+    type Err = RuleParseError;
+
+    // Parses the part of a rule after the colon, e.g. T30,20 or K30*,20
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || RuleParseError::new(format!("Unsupported topology \"{s}\""));
+
+        let mut chars = s.chars();
+        let kind = chars.next().ok_or_else(err)?.to_ascii_uppercase();
+        let dimensions = chars.as_str();
+
+        let parse_dimension = |dimension: &str| {
+            let (dimension, twisted) = match dimension.trim().strip_suffix('*') {
+                Some(dimension) => (dimension, true),
+                None => (dimension.trim(), false),
+            };
+            // Golly uses a size of zero for unbounded directions, which isn't supported
+            match dimension.parse::<u32>() {
+                Ok(size) if size > 0 => Ok((size, twisted)),
+                _ => Err(err()),
+            }
+        };
+
+        if kind == 'S' {
+            return match parse_dimension(dimensions)? {
+                (size, false) => Ok(Topology::Sphere { size }),
+                _ => Err(err()),
+            };
+        }
+
+        let (width, height) = dimensions.split_once(',').ok_or_else(err)?;
+        let (width, twisted_width) = parse_dimension(width)?;
+        let (height, twisted_height) = parse_dimension(height)?;
+
+        match (kind, twisted_width, twisted_height) {
+            ('P', false, false) => Ok(Topology::Plane { width, height }),
+            ('T', false, false) => Ok(Topology::Torus { width, height }),
+            ('K', true, false) => Ok(Topology::KleinBottle {
+                width,
+                height,
+                twisted: TwistedEdges::TopBottom,
+            }),
+            ('K', false, true) => Ok(Topology::KleinBottle {
+                width,
+                height,
+                twisted: TwistedEdges::LeftRight,
+            }),
+            _ => Err(err()),
+        }
+    }
+}
+
+impl fmt::Display for Topology {
+    // Written as a rule suffix, so the infinite plane is empty
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Topology::Infinite => Ok(()),
+            Topology::Plane { width, height } => write!(f, ":P{width},{height}"),
+            Topology::Torus { width, height } => write!(f, ":T{width},{height}"),
+            Topology::KleinBottle {
+                width,
+                height,
+                twisted: TwistedEdges::TopBottom,
+            } => write!(f, ":K{width}*,{height}"),
+            Topology::KleinBottle {
+                width,
+                height,
+                twisted: TwistedEdges::LeftRight,
+            } => write!(f, ":K{width},{height}*"),
+            Topology::Sphere { size } => write!(f, ":S{size}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn topologies_round_trip() {
+        for topology in [":P30,20", ":T8,8", ":K10*,12", ":K10,12*", ":S7"] {
+            let parsed: Topology = topology[1..].parse().unwrap();
+            assert_eq!(parsed.to_string(), topology);
+        }
+        assert_eq!(
+            "t5,6".parse(),
+            Ok(Topology::Torus {
+                width: 5,
+                height: 6
+            })
+        );
+
+        for topology in ["T0,20", "K10,12", "S5*", "P10", "Q3,3"] {
+            assert!(topology.parse::<Topology>().is_err());
+        }
+    }
+
+    #[test]
+    fn wrapping_joins_the_right_edges() {
+        let torus = Topology::Torus {
+            width: 4,
+            height: 3,
+        };
+        assert_eq!(torus.wrap(-1, -1), Some((3, 2)));
+        assert_eq!(torus.wrap(4, 1), Some((0, 1)));
+
+        let klein = Topology::KleinBottle {
+            width: 4,
+            height: 3,
+            twisted: TwistedEdges::TopBottom,
+        };
+        assert_eq!(klein.wrap(0, -1), Some((3, 2)));
+        assert_eq!(klein.wrap(-1, 1), Some((3, 1)));
+
+        let sphere = Topology::Sphere { size: 4 };
+        assert_eq!(sphere.wrap(-1, 2), Some((2, 0)));
+        assert_eq!(sphere.wrap(2, -1), Some((0, 2)));
+        assert_eq!(sphere.wrap(4, 1), Some((1, 3)));
+        assert_eq!(sphere.wrap(-2, 1), Some((1, 1)));
+        assert_eq!(sphere.wrap(1, 5), Some((2, 1)));
+        assert_eq!(sphere.wrap(-1, -1), None);
+
+        assert_eq!(
+            Topology::Plane {
+                width: 4,
+                height: 3
+            }
+            .wrap(-1, 0),
+            None
+        );
+    }
+}
@@ -0,0 +1,212 @@
+// Rotating, flipping and moving universes. Each transform rewrites a cell from its
+// transformed children, so every distinct cell is only rewritten once.
+
+use std::collections::HashMap;
+
+use super::{cell::Cell, cell_id::CellId, leaf::LeafCell, Life};
+
+/// Rotations are clockwise as displayed, with y growing downwards.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Transform {
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    FlipHorizontal,
+    FlipVertical,
+    Transpose,
+}
+
+impl Transform {
+    /// Where the cell at (x, y) ends up, turning about the corner shared by the four
+    /// cells around the origin.
+    pub fn apply(self, (x, y): (i64, i64)) -> (i64, i64) {
+        match self {
+            Transform::Rotate90 => (-y - 1, x),
+            Transform::Rotate180 => (-x - 1, -y - 1),
+            Transform::Rotate270 => (y, -x - 1),
+            Transform::FlipHorizontal => (-x - 1, y),
+            Transform::FlipVertical => (x, -y - 1),
+            Transform::Transpose => (y, x),
+        }
+    }
+
+    fn leaf(self, leaf: &LeafCell) -> LeafCell {
+        match self {
+            Transform::Rotate90 => leaf.transposed().flipped_horizontally(),
+            Transform::Rotate180 => leaf.flipped_horizontally().flipped_vertically(),
+            Transform::Rotate270 => leaf.transposed().flipped_vertically(),
+            Transform::FlipHorizontal => leaf.flipped_horizontally(),
+            Transform::FlipVertical => leaf.flipped_vertically(),
+            Transform::Transpose => leaf.transposed(),
+        }
+    }
+
+    // The quadrants which each quadrant is taken from
+    fn quadrants(self, [nw, ne, sw, se]: [CellId; 4]) -> [CellId; 4] {
+        match self {
+            Transform::Rotate90 => [sw, nw, se, ne],
+            Transform::Rotate180 => [se, sw, ne, nw],
+            Transform::Rotate270 => [ne, se, nw, sw],
+            Transform::FlipHorizontal => [ne, nw, se, sw],
+            Transform::FlipVertical => [sw, se, nw, ne],
+            Transform::Transpose => [nw, sw, ne, se],
+        }
+    }
+}
+
+impl Life {
+    /// Transforms every cell of the universe as `Transform::apply` does. Cells which
+    /// end up outside a bounded grid are removed.
+    pub fn transform(&mut self, transform: Transform) {
+        let (x, y) = self.root_corner();
+        let far = (1 << self.root.layer()) - 1;
+        let (first, second) = (transform.apply((x, y)), transform.apply((x + far, y + far)));
+        let half_width = (far + 1) / 2;
+        self.root_centre = (
+            first.0.min(second.0) + half_width,
+            first.1.min(second.1) + half_width,
+        );
+
+        self.root = self.transformed(self.root, transform);
+        self.clear_outside_grid();
+    }
+
+    /// The cell transformed within its own square.
+    pub fn transformed(&self, cell_id: CellId, transform: Transform) -> CellId {
+        self.transformed_cell(cell_id, transform, &mut HashMap::new())
+    }
+
+    fn transformed_cell(
+        &self,
+        cell_id: CellId,
+        transform: Transform,
+        transformed: &mut HashMap<CellId, CellId>,
+    ) -> CellId {
+        if cell_id.alive() == 0 {
+            return cell_id;
+        }
+        if let Some(&id) = transformed.get(&cell_id) {
+            return id;
+        }
+
+        let res = match self.get_cell(cell_id).unwrap() {
+            Cell::Base(_) => cell_id,
+            Cell::Leaf(leaf) => self.add_cell(Cell::Leaf(transform.leaf(&leaf))),
+            Cell::Composite(cell) => {
+                let quadrants = transform.quadrants([cell.nw, cell.ne, cell.sw, cell.se]);
+                let [nw, ne, sw, se] = quadrants
+                    .map(|quadrant| self.transformed_cell(quadrant, transform, transformed));
+                self.join(nw, ne, sw, se)
+            }
+        };
+        transformed.insert(cell_id, res);
+        res
+    }
+
+    /// Moves every cell by (dx, dy). Cells which end up outside a bounded grid are
+    /// removed.
+    pub fn translate(&mut self, dx: i64, dy: i64) {
+        // Moving the root by whole leaves keeps them on their grid, what is left over is
+        // shifted within the root
+        let leaf_width = 1 << self.leaf_layer;
+        let (x, y) = (dx.rem_euclid(leaf_width), dy.rem_euclid(leaf_width));
+        self.root_centre.0 += dx - x;
+        self.root_centre.1 += dy - y;
+
+        if (x, y) != (0, 0) {
+            // Padding leaves room for the shift at the edges of the root, as long as the
+            // root started out wider than a leaf
+            self.root = self.padded(self.root);
+            while self.root.layer() < self.leaf_layer + 2 {
+                self.root = self.padded(self.root);
+            }
+            let (root_x, root_y) = self.root_corner();
+            self.root = self.placed(self.root, (root_x + x, root_y + y));
+        }
+        self.clear_outside_grid();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::life::pack_unpack::{test::assert_same_cells, CellBounds};
+    use std::collections::HashSet;
+
+    const TRANSFORMS: [Transform; 6] = [
+        Transform::Rotate90,
+        Transform::Rotate180,
+        Transform::Rotate270,
+        Transform::FlipHorizontal,
+        Transform::FlipVertical,
+        Transform::Transpose,
+    ];
+
+    #[test]
+    fn transforms_move_cells_like_apply() {
+        let cells: Vec<_> = (-20..25)
+            .flat_map(|x: i64| (-9..30).map(move |y: i64| (x, y)))
+            .filter(|(x, y)| (x * x + 3 * y).rem_euclid(7) < 2)
+            .collect();
+        for leaf_size in [1, 2, 8, 16] {
+            for transform in TRANSFORMS {
+                let mut life = Life::from_cell_positions(cells.clone());
+                life.set_leaf_size(leaf_size);
+                life.transform(transform);
+
+                let expected: HashSet<_> =
+                    cells.iter().map(|&cell| transform.apply(cell)).collect();
+                let actual: HashSet<_> = life
+                    .cell_positions(0, CellBounds::ALL)
+                    .into_iter()
+                    .collect();
+                assert_eq!(actual, expected, "{transform:?} with leaf size {leaf_size}");
+            }
+        }
+
+        // Four quarter turns come back to the same cell
+        let mut life = Life::from_cell_positions(cells);
+        let root = life.root;
+        for _ in 0..4 {
+            life.root = life.transformed(life.root, Transform::Rotate90);
+        }
+        assert_eq!(life.root, root);
+    }
+
+    #[test]
+    fn transformed_patterns_step_like_fresh_ones() {
+        let r_pentomino = [(1, 0), (2, 0), (0, 1), (1, 1), (1, 2)];
+        for transform in TRANSFORMS {
+            let mut transformed = Life::from_cell_positions(r_pentomino.to_vec());
+            transformed.transform(transform);
+            transformed.translate(-37, 5);
+            transformed.step_by(200).unwrap();
+
+            let moved = r_pentomino.map(|cell| {
+                let (x, y) = transform.apply(cell);
+                (x - 37, y + 5)
+            });
+            let mut fresh = Life::from_cell_positions(moved.to_vec());
+            fresh.step_by(200).unwrap();
+
+            assert_same_cells(&transformed, &fresh);
+        }
+    }
+
+    #[test]
+    fn translated_cells_keep_their_scaled_positions() {
+        let mut life = Life::from_cell_positions(vec![(0, 0), (3, 5), (-6, 2)]);
+        life.translate(13, -3);
+        let positions: HashSet<_> = life
+            .cell_positions(0, CellBounds::ALL)
+            .into_iter()
+            .collect();
+        assert_eq!(positions, HashSet::from([(13, -3), (16, 2), (7, -1)]));
+
+        let scaled: HashSet<_> = life
+            .cell_positions(2, CellBounds::ALL)
+            .into_iter()
+            .collect();
+        assert_eq!(scaled, HashSet::from([(3, -1), (4, 0), (1, -1)]));
+    }
+}
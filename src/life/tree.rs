@@ -1,9 +1,9 @@
 use crate::life::cell::Cell;
 
-use super::{cell_id::CellId, Life};
+use super::{cell_id::CellId, leaf::LeafCell, Life};
 
 impl Life {
-    pub fn join(&mut self, nw: CellId, ne: CellId, sw: CellId, se: CellId) -> CellId {
+    pub fn join(&self, nw: CellId, ne: CellId, sw: CellId, se: CellId) -> CellId {
         debug_assert_eq!(nw.layer(), ne.layer());
         debug_assert_eq!(nw.layer(), sw.layer());
         debug_assert_eq!(nw.layer(), se.layer());
@@ -11,71 +11,183 @@ impl Life {
         self.add_cell(Cell::composite(nw, ne, sw, se))
     }
 
-    pub fn centered_subnode(&mut self, id: CellId) -> CellId {
-        let cell = self.get_cell(id).unwrap().as_composite();
+    pub fn centered_subnode(&self, id: CellId) -> CellId {
+        let cell = self.get_cell(id).unwrap().into_composite();
 
-        let nw = self.get_cell(cell.nw).unwrap().as_composite().se;
-        let ne = self.get_cell(cell.ne).unwrap().as_composite().sw;
-        let sw = self.get_cell(cell.sw).unwrap().as_composite().ne;
-        let se = self.get_cell(cell.se).unwrap().as_composite().nw;
+        if let Cell::Leaf(_) = self.get_cell(cell.nw).unwrap() {
+            let [nw, ne, sw, se] = [cell.nw, cell.ne, cell.sw, cell.se]
+                .map(|child| self.get_cell(child).unwrap().into_leaf());
+            let leaf = LeafCell::centered([&nw, &ne, &sw, &se]);
+            return self.add_cell(Cell::Leaf(leaf));
+        }
+
+        let nw = self.get_cell(cell.nw).unwrap().into_composite().se;
+        let ne = self.get_cell(cell.ne).unwrap().into_composite().sw;
+        let sw = self.get_cell(cell.sw).unwrap().into_composite().ne;
+        let se = self.get_cell(cell.se).unwrap().into_composite().nw;
 
         self.join(nw, ne, sw, se)
     }
 
-    pub fn centered_sub_subnode(
-        &mut self,
-        nw: CellId,
-        ne: CellId,
-        sw: CellId,
-        se: CellId,
-    ) -> CellId {
+    pub fn centered_sub_subnode(&self, nw: CellId, ne: CellId, sw: CellId, se: CellId) -> CellId {
         assert!(nw.layer() > 0);
 
         let nw = self
-            .get_cell(self.get_cell(nw).unwrap().as_composite().se)
+            .get_cell(self.get_cell(nw).unwrap().into_composite().se)
             .unwrap()
-            .as_composite()
+            .into_composite()
             .se;
         let ne = self
-            .get_cell(self.get_cell(ne).unwrap().as_composite().sw)
+            .get_cell(self.get_cell(ne).unwrap().into_composite().sw)
             .unwrap()
-            .as_composite()
+            .into_composite()
             .sw;
         let sw = self
-            .get_cell(self.get_cell(sw).unwrap().as_composite().ne)
+            .get_cell(self.get_cell(sw).unwrap().into_composite().ne)
             .unwrap()
-            .as_composite()
+            .into_composite()
             .ne;
         let se = self
-            .get_cell(self.get_cell(se).unwrap().as_composite().nw)
+            .get_cell(self.get_cell(se).unwrap().into_composite().nw)
             .unwrap()
-            .as_composite()
+            .into_composite()
             .nw;
 
         self.join(nw, ne, sw, se)
     }
 
-    pub fn centered_horizontal(&mut self, w: CellId, e: CellId) -> CellId {
-        let w = self.get_cell(w).unwrap().as_composite();
-        let e = self.get_cell(e).unwrap().as_composite();
+    pub fn centered_horizontal(&self, w: CellId, e: CellId) -> CellId {
+        let w = self.get_cell(w).unwrap().into_composite();
+        let e = self.get_cell(e).unwrap().into_composite();
 
         self.join(
-            self.get_cell(w.ne).unwrap().as_composite().se,
-            self.get_cell(e.nw).unwrap().as_composite().sw,
-            self.get_cell(w.se).unwrap().as_composite().ne,
-            self.get_cell(e.sw).unwrap().as_composite().nw,
+            self.get_cell(w.ne).unwrap().into_composite().se,
+            self.get_cell(e.nw).unwrap().into_composite().sw,
+            self.get_cell(w.se).unwrap().into_composite().ne,
+            self.get_cell(e.sw).unwrap().into_composite().nw,
         )
     }
 
-    pub fn centered_vertical(&mut self, n: CellId, s: CellId) -> CellId {
-        let n = self.get_cell(n).unwrap().as_composite();
-        let s = self.get_cell(s).unwrap().as_composite();
+    pub fn centered_vertical(&self, n: CellId, s: CellId) -> CellId {
+        let n = self.get_cell(n).unwrap().into_composite();
+        let s = self.get_cell(s).unwrap().into_composite();
 
         self.join(
-            self.get_cell(n.sw).unwrap().as_composite().se,
-            self.get_cell(n.se).unwrap().as_composite().sw,
-            self.get_cell(s.nw).unwrap().as_composite().ne,
-            self.get_cell(s.ne).unwrap().as_composite().nw,
+            self.get_cell(n.sw).unwrap().into_composite().se,
+            self.get_cell(n.se).unwrap().into_composite().sw,
+            self.get_cell(s.nw).unwrap().into_composite().ne,
+            self.get_cell(s.ne).unwrap().into_composite().nw,
         )
     }
+
+    // The nine overlapping leaves centred within a cell two layers above the leaves,
+    // row by row. They straddle the leaves of the cell, so they can't be joined from them.
+    pub fn centered_leaves(&self, id: CellId) -> [CellId; 9] {
+        let cell = self.get_cell(id).unwrap().into_composite();
+        let [nw, ne, sw, se] = [cell.nw, cell.ne, cell.sw, cell.se]
+            .map(|child| self.get_cell(child).unwrap().into_composite());
+
+        let leaves = [
+            [nw.nw, nw.ne, ne.nw, ne.ne],
+            [nw.sw, nw.se, ne.sw, ne.se],
+            [sw.nw, sw.ne, se.nw, se.ne],
+            [sw.sw, sw.se, se.sw, se.se],
+        ]
+        .map(|row| row.map(|leaf| self.get_cell(leaf).unwrap().into_leaf()));
+
+        std::array::from_fn(|i| {
+            let (x, y) = (i % 3, i / 3);
+            let quadrants = [
+                &leaves[y][x],
+                &leaves[y][x + 1],
+                &leaves[y + 1][x],
+                &leaves[y + 1][x + 1],
+            ];
+            self.add_cell(Cell::Leaf(LeafCell::centered(quadrants)))
+        })
+    }
+
+    // Returns the square straddling the border between two horizontally adjacent cells
+    pub fn horizontal(&self, w: CellId, e: CellId) -> CellId {
+        let w = self.get_cell(w).unwrap().into_composite();
+        let e = self.get_cell(e).unwrap().into_composite();
+
+        self.join(w.ne, e.nw, w.se, e.sw)
+    }
+
+    // Returns the square straddling the border between two vertically adjacent cells
+    pub fn vertical(&self, n: CellId, s: CellId) -> CellId {
+        let n = self.get_cell(n).unwrap().into_composite();
+        let s = self.get_cell(s).unwrap().into_composite();
+
+        self.join(n.sw, n.se, s.nw, s.ne)
+    }
+
+    // Writes the state of every cell into a grid of rows of `width`, which must
+    // start out dead
+    pub fn read_states(
+        &self,
+        cell_id: CellId,
+        grid: &mut [u8],
+        width: usize,
+        (x, y): (usize, usize),
+    ) {
+        if cell_id.alive() == 0 {
+            return;
+        }
+
+        let cell = match self.get_cell(cell_id).unwrap() {
+            Cell::Base(cell) => {
+                grid[y * width + x] = cell.state();
+                return;
+            }
+            Cell::Leaf(leaf) => {
+                let leaf_width = 1 << leaf.layer();
+                for dy in 0..leaf_width {
+                    for dx in 0..leaf_width {
+                        grid[(y + dy) * width + x + dx] = leaf.state(dx, dy);
+                    }
+                }
+                return;
+            }
+            Cell::Composite(cell) => cell,
+        };
+
+        let half_width = 1 << (cell_id.layer() - 1);
+        self.read_states(cell.nw, grid, width, (x, y));
+        self.read_states(cell.ne, grid, width, (x + half_width, y));
+        self.read_states(cell.sw, grid, width, (x, y + half_width));
+        self.read_states(cell.se, grid, width, (x + half_width, y + half_width));
+    }
+
+    // Builds a cell of the given layer from the square of a grid with its top left corner
+    // at the given position
+    pub fn build_from_states(
+        &self,
+        grid: &[u8],
+        width: usize,
+        (x, y): (usize, usize),
+        layer: usize,
+    ) -> CellId {
+        if layer == 0 {
+            return self.base_cell(grid[y * width + x]);
+        }
+
+        if layer == self.leaf_layer {
+            let mut leaf = LeafCell::empty(layer);
+            for dy in 0..1 << layer {
+                for dx in 0..1 << layer {
+                    leaf = leaf.with_state(dx, dy, grid[(y + dy) * width + x + dx]);
+                }
+            }
+            return self.add_cell(Cell::Leaf(leaf));
+        }
+
+        let half_width = 1 << (layer - 1);
+        let nw = self.build_from_states(grid, width, (x, y), layer - 1);
+        let ne = self.build_from_states(grid, width, (x + half_width, y), layer - 1);
+        let sw = self.build_from_states(grid, width, (x, y + half_width), layer - 1);
+        let se = self.build_from_states(grid, width, (x + half_width, y + half_width), layer - 1);
+        self.join(nw, ne, sw, se)
+    }
 }
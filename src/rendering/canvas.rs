@@ -8,6 +8,7 @@ pub trait Canvas {
 
     fn set(&mut self, x: usize, y: usize);
     fn clear(&mut self);
+    fn invert(&mut self);
 
     fn render(&self, output: &mut impl Write);
 }
@@ -89,6 +89,12 @@ impl Canvas for DetailedCanvas {
         }
     }
 
+    fn invert(&mut self) {
+        for c in &mut self.buffer {
+            *c = !*c;
+        }
+    }
+
     fn render(&self, output: &mut impl Write) {
         let (width, height) = self.char_size();
         let mut buffer = String::new();
@@ -8,7 +8,7 @@ use crossterm::{
     terminal::disable_raw_mode,
 };
 
-use crate::life::{pack_unpack::CellBounds, Life};
+use crate::life::{pack_unpack::CellBounds, rule::Neighbourhood, Life};
 
 use super::{canvas::Canvas, detailed_canvas::DetailedCanvas};
 enum RunningState {
@@ -104,8 +104,9 @@ impl LifeViewer {
 
     pub fn step(&mut self, output: &mut StdoutLock) {
         let step_start = std::time::Instant::now();
-        if matches!(self.running_state, RunningState::Running) {
-            self.life.step();
+        // A pattern which has run out of coordinates can still be looked at
+        if matches!(self.running_state, RunningState::Running) && self.life.step().is_err() {
+            self.running_state = RunningState::Paused;
         }
         let step_time = step_start.elapsed();
 
@@ -122,45 +123,20 @@ impl LifeViewer {
             MoveTo(0, 2),
             Print(&format!("Alive: {}\n", self.life.root.alive())),
             MoveTo(0, 3),
-            Print(&format!("Zoom: 1/{}\n", self.pixel_scale())),
+            Print(&format!("Generation: {}\n", self.life.generation())),
             MoveTo(0, 4),
-            Print(&format!("Layer 0: {}\n", self.life.layers[0].calls())),
-            MoveTo(0, 5),
-            Print(&format!("Layer 1: {}\n", self.life.layers[1].calls())),
-            MoveTo(0, 6),
-            Print(&format!("Layer 2: {}\n", self.life.layers[2].calls())),
-            MoveTo(0, 7),
-            Print(&format!("Layer 3: {}\n", self.life.layers[3].calls())),
-            MoveTo(0, 8),
-            Print(&format!("Layer 4: {}\n", self.life.layers[4].calls())),
-            MoveTo(0, 9),
-            Print(&format!("Layer 5: {}\n", self.life.layers[5].calls())),
-            MoveTo(0, 10),
-            Print(&format!("Layer 6: {}\n", self.life.layers[6].calls())),
-            MoveTo(0, 11),
-            Print(&format!("Layer 7: {}\n", self.life.layers[7].calls())),
-            MoveTo(0, 12),
-            Print(&format!("Layer 8: {}\n", self.life.layers[8].calls())),
-            MoveTo(0, 13),
-            Print(&format!("Layer 9: {}\n", self.life.layers[9].calls())),
-            MoveTo(0, 14),
-            Print(&format!("Layer 10: {}\n", self.life.layers[10].calls())),
-            MoveTo(0, 15),
-            Print(&format!("Layer 11: {}\n", self.life.layers[11].calls())),
-            MoveTo(0, 16),
-            Print(&format!("Layer 12: {}\n", self.life.layers[12].calls())),
-            MoveTo(0, 17),
-            Print(&format!("Layer 13: {}\n", self.life.layers[13].calls())),
-            MoveTo(0, 18),
-            Print(&format!("Layer 14: {}\n", self.life.layers[14].calls())),
-            MoveTo(0, 19),
-            Print(&format!("Layer 15: {}\n", self.life.layers[15].calls())),
-            MoveTo(0, 20),
-            Print(&format!("Layer 16: {}\n", self.life.layers[16].calls())),
-            MoveTo(0, 21),
-            Print(&format!("Layer 17: {}\n", self.life.layers[17].calls())),
+            Print(&format!("Zoom: 1/{}\n", self.pixel_scale())),
         )
         .unwrap();
+
+        for (i, layer) in self.life.layers.iter().enumerate() {
+            queue!(
+                output,
+                MoveTo(0, 5 + i as u16),
+                Print(&format!("Layer {i}: {}\n", layer.calls())),
+            )
+            .unwrap();
+        }
     }
 
     pub fn pixel_scale(&self) -> i64 {
@@ -183,22 +159,41 @@ impl LifeViewer {
         }
     }
 
+    // Hexagonal rules are drawn with each row shifted half a cell left of the one above,
+    // which puts a cell at (2x - y, y) in half cell units
+    fn is_hexagonal(&self) -> bool {
+        self.life.rule().neighbourhood() == Neighbourhood::HEXAGONAL
+    }
+
     pub fn render(&mut self, output: &mut impl Write) {
         let pixel_scale = self.pixel_scale();
-        let positions = self
-            .life
-            .cell_positions(self.render_depth, self.cell_bounds());
+        let hexagonal = self.is_hexagonal();
+
+        let mut bounds = self.cell_bounds();
+        if hexagonal {
+            (bounds.min_x, bounds.max_x) = (
+                (bounds.min_x + bounds.min_y).div_euclid(2),
+                (bounds.max_x + bounds.max_y).div_euclid(2) + 1,
+            );
+        }
+        let positions = self.life.cell_positions(self.render_depth, bounds);
 
         self.canvas.clear();
 
-        let offset_x = self.cell_offset_x / pixel_scale;
-        let offset_y = self.cell_offset_y / pixel_scale;
+        let offset_x = self.cell_offset_x.div_euclid(pixel_scale);
+        let offset_y = self.cell_offset_y.div_euclid(pixel_scale);
 
-        for (x, y) in positions.iter() {
+        for &(x, y) in positions.iter() {
+            let x = if hexagonal { 2 * x - y } else { x };
             self.canvas
                 .set((x - offset_x) as usize, (y - offset_y) as usize);
         }
 
+        // Rules with B0 list the dead cells on generations where empty space is alive
+        if self.life.background_state() != 0 {
+            self.canvas.invert();
+        }
+
         self.canvas.render(output);
     }
 }